@@ -0,0 +1,281 @@
+//! The `AFP_AfpInfo` stream, through which Samba's `vfs_fruit` module exposes FinderInfo to macOS
+//! SMB clients.
+//!
+//! The stream is 60 bytes: a signature, version, backup date, the 32 bytes of FinderInfo and ProDOS
+//! info. With `fruit:metadata = stream` Samba keeps it in the `user.DosStream.AFP_AfpInfo:$DATA`
+//! extended attribute (see `SambaStore`), with a NUL appended by `vfs_streams_xattr`. With
+//! `fruit:metadata = netatalk` it uses netatalk's attribute instead; see `netatalk::NetatalkStore`.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use crate::appledouble::FileDates;
+use crate::{be_u32, Error, FinderInfo, FinderInfoKind, Result};
+
+/// Length of the stream.
+pub const LEN: usize = 60;
+/// `'AFP\0'`.
+pub const SIGNATURE: u32 = 0x4146_5000;
+pub const VERSION: u32 = 0x0000_0100;
+
+/// The contents of an `AFP_AfpInfo` stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AfpInfo {
+    /// Reserved; Samba writes 0.
+    pub file_id: u32,
+    /// Seconds since 2000-01-01 00:00:00 UTC, or `FileDates::UNKNOWN`. See
+    /// `appledouble::date_to_system_time`.
+    pub backup_time: i32,
+    pub finder_info: FinderInfo,
+    pub prodos_info: [u8; 6],
+}
+
+impl AfpInfo {
+    /// Creates the stream Samba would for `finder_info`, with no backup date.
+    pub fn new(finder_info: FinderInfo) -> AfpInfo {
+        AfpInfo {
+            file_id: 0,
+            backup_time: FileDates::UNKNOWN,
+            finder_info,
+            prodos_info: [0u8; 6],
+        }
+    }
+
+    /// Reads the stream to the end of `r`, interpreting the FinderInfo as `kind`.
+    pub fn read_as<R: Read>(kind: FinderInfoKind, r: &mut R) -> Result<AfpInfo> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        AfpInfo::from_slice_as(kind, &buf)
+    }
+
+    pub fn from_slice_as(kind: FinderInfoKind, buf: &[u8]) -> Result<AfpInfo> {
+        if buf.len() != LEN {
+            return Err(Error::InvalidLength {
+                expected: LEN,
+                actual: buf.len(),
+            });
+        }
+        let signature = be_u32(buf, 0);
+        if signature != SIGNATURE {
+            return Err(Error::InvalidMagic { found: signature });
+        }
+        let version = be_u32(buf, 4);
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion { version });
+        }
+        let finder_info = <&[u8; 32]>::try_from(&buf[16..48]).expect("slice is 32 bytes");
+        let prodos_info = <[u8; 6]>::try_from(&buf[48..54]).expect("slice is 6 bytes");
+        Ok(AfpInfo {
+            file_id: be_u32(buf, 8),
+            backup_time: be_u32(buf, 12) as i32,
+            finder_info: FinderInfo::from_bytes_as(kind, finder_info),
+            prodos_info,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; LEN] {
+        let mut buf = [0u8; LEN];
+        buf[0..4].copy_from_slice(&SIGNATURE.to_be_bytes());
+        buf[4..8].copy_from_slice(&VERSION.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.file_id.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.backup_time.to_be_bytes());
+        buf[16..48].copy_from_slice(&self.finder_info.to_bytes());
+        buf[48..54].copy_from_slice(&self.prodos_info);
+        buf
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
+}
+
+#[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+pub use self::store::{SambaStore, ATTRIBUTE_NAME};
+
+#[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+mod store {
+    use std::path::Path;
+
+    use super::{AfpInfo, LEN};
+    use crate::store::FinderInfoStore;
+    use crate::xattr::{self, SetMode};
+    use crate::{kind_of_path, Error, FinderInfo, Result};
+
+    /// Name of the attribute `vfs_streams_xattr` keeps the stream in.
+    pub const ATTRIBUTE_NAME: &str = "user.DosStream.AFP_AfpInfo:$DATA";
+
+    /// Reads and writes FinderInfo in the `AFP_AfpInfo` stream as Samba stores it on Linux.
+    ///
+    /// Writing keeps the backup date and ProDOS info of an existing stream. Removing FinderInfo
+    /// removes the whole stream, as Samba does when a client clears it, and all-zero FinderInfo
+    /// counts as none.
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub struct SambaStore {
+        name: String,
+    }
+
+    impl Default for SambaStore {
+        fn default() -> SambaStore {
+            SambaStore::new()
+        }
+    }
+
+    impl SambaStore {
+        pub fn new() -> SambaStore {
+            SambaStore::with_name(ATTRIBUTE_NAME)
+        }
+
+        /// Uses the attribute `name`, for shares with a non-default `streams_xattr:prefix`.
+        pub fn with_name(name: impl Into<String>) -> SambaStore {
+            SambaStore { name: name.into() }
+        }
+
+        /// Reads the whole stream, or `None` if `path` doesn't have one.
+        pub fn afp_info(&self, path: &Path) -> Result<Option<AfpInfo>> {
+            let buf = match xattr::get(path, &self.name)? {
+                Some(buf) => buf,
+                None => return Ok(None),
+            };
+            // `vfs_streams_xattr` stores streams with a trailing NUL.
+            match buf.split_last() {
+                Some((0, stream)) if buf.len() == LEN + 1 => {
+                    AfpInfo::from_slice_as(kind_of_path(path)?, stream).map(Some)
+                }
+                _ => Err(Error::InvalidLength {
+                    expected: LEN + 1,
+                    actual: buf.len(),
+                }),
+            }
+        }
+
+        pub fn set_afp_info(&self, path: &Path, afp_info: &AfpInfo) -> Result<()> {
+            let mut buf = afp_info.to_bytes().to_vec();
+            buf.push(0);
+            xattr::set(path, &self.name, &buf, SetMode::CreateOrReplace)
+        }
+    }
+
+    impl FinderInfoStore for SambaStore {
+        fn get(&self, path: &Path) -> Result<Option<FinderInfo>> {
+            Ok(self
+                .afp_info(path)?
+                .map(|ai| ai.finder_info)
+                .filter(|fi| fi.to_bytes() != [0u8; 32]))
+        }
+
+        fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()> {
+            let afp_info = match self.afp_info(path)? {
+                Some(existing) => AfpInfo {
+                    finder_info: *finder_info,
+                    ..existing
+                },
+                None => AfpInfo::new(*finder_info),
+            };
+            self.set_afp_info(path, &afp_info)
+        }
+
+        fn remove(&self, path: &Path) -> Result<bool> {
+            // A stream which can't be parsed is removed too, and counts as FinderInfo.
+            let existed = !matches!(self.get(path), Ok(None));
+            Ok(xattr::remove(path, &self.name)? && existed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FinderInfoFile, FinderInfoFolder, OSType};
+
+    #[test]
+    fn test_afpinfo_roundtrip() {
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(b"TEXT");
+        file.file_info.fileCreator = OSType::new(b"ttxt");
+        let afp_info = AfpInfo::new(FinderInfo::File(file));
+        let buf = afp_info.to_bytes();
+        assert_eq!(&buf[..16], b"AFP\0\0\0\x01\0\0\0\0\0\x80\0\0\0");
+        assert_eq!(&buf[16..24], b"TEXTttxt");
+        assert_eq!(
+            AfpInfo::read_as(FinderInfoKind::File, &mut &buf[..]).unwrap(),
+            afp_info
+        );
+
+        let folder = AfpInfo::from_slice_as(FinderInfoKind::Directory, &buf).unwrap();
+        assert_eq!(folder.finder_info.kind(), Some(FinderInfoKind::Directory));
+        assert_eq!(
+            AfpInfo::new(FinderInfo::Directory(FinderInfoFolder::default())).to_bytes()[16..48],
+            [0u8; 32]
+        );
+
+        let mut bad = buf;
+        bad[3] = b'X';
+        match AfpInfo::from_slice_as(FinderInfoKind::File, &bad) {
+            Err(Error::InvalidMagic { found: 0x4146_5058 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut bad = buf;
+        bad[6] = 2;
+        match AfpInfo::from_slice_as(FinderInfoKind::File, &bad) {
+            Err(Error::UnsupportedVersion {
+                version: 0x0000_0200,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match AfpInfo::from_slice_as(FinderInfoKind::File, &buf[..59]) {
+            Err(Error::InvalidLength {
+                expected: 60,
+                actual: 59,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+    #[test]
+    fn test_samba_store() {
+        use crate::store::FinderInfoStore;
+        use crate::test_util::ScratchDir;
+        use std::fs;
+
+        let dir = match ScratchDir::with_xattrs("samba") {
+            Some(dir) => dir,
+            None => return,
+        };
+        let path = dir.join("file");
+        fs::write(&path, b"").unwrap();
+        let store = SambaStore::new();
+
+        let mut existing = AfpInfo::new(FinderInfo::File(FinderInfoFile::default()));
+        existing.backup_time = 12345;
+        store.set_afp_info(&path, &existing).unwrap();
+        let raw = crate::xattr::get(&path, ATTRIBUTE_NAME).unwrap().unwrap();
+        assert_eq!(raw.len(), 61);
+        assert_eq!(store.get(&path).unwrap(), None);
+
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(b"PDF ");
+        store.set(&path, &FinderInfo::File(file)).unwrap();
+        assert_eq!(store.get(&path).unwrap(), Some(FinderInfo::File(file)));
+        assert_eq!(store.afp_info(&path).unwrap().unwrap().backup_time, 12345);
+
+        crate::xattr::set(
+            &path,
+            ATTRIBUTE_NAME,
+            &[0u8; 10],
+            crate::xattr::SetMode::Replace,
+        )
+        .unwrap();
+        match store.get(&path) {
+            Err(Error::InvalidLength {
+                expected: 61,
+                actual: 10,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        assert!(store.remove(&path).unwrap());
+        assert!(!store.remove(&path).unwrap());
+        assert!(crate::xattr::get(&path, ATTRIBUTE_NAME).unwrap().is_none());
+    }
+}
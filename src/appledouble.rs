@@ -0,0 +1,831 @@
+//! AppleDouble files, the `._name` sidecars macOS writes next to files on volumes which can't store
+//! FinderInfo, resource forks or extended attributes natively.
+//!
+//! An AppleDouble file is a 26 byte header (magic, version and a 16 byte filler), followed by a
+//! table of entry descriptors (ID, offset, length) and then the entry data. The FinderInfo entry
+//! (ID 9) starts with the same 32 bytes as `com.apple.FinderInfo`.
+//!
+//! Entries are kept in the order they were read and written out back to back in that order, which
+//! is how macOS lays them out, so unmodified sidecars round-trip byte for byte.
+//!
+//! macOS stores the extended attributes of the file in an "ATTR" block after the 32 FinderInfo
+//! bytes, inside the FinderInfo entry. The block uses absolute file offsets, which would break as
+//! soon as an entry before it changed size, so while the file is in memory those offsets are kept
+//! relative to the start of the FinderInfo entry. They are converted back when the file is written.
+
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{be_u16, be_u32, macroman, Error, FinderInfo, FinderInfoKind, Result};
+
+/// Magic number at the start of an AppleDouble file.
+pub const MAGIC: u32 = 0x0005_1607;
+/// Version 1 of the format, from A/UX.
+pub const VERSION_1: u32 = 0x0001_0000;
+/// Version 2 of the format, which macOS writes.
+pub const VERSION_2: u32 = 0x0002_0000;
+/// The filler macOS writes: the name of the home file system, padded with spaces.
+pub const MACOS_FILLER: [u8; 16] = *b"Mac OS X        ";
+
+const HEADER_LEN: usize = 26;
+const DESCRIPTOR_LEN: usize = 12;
+
+/// Magic number of the extended attribute block in the FinderInfo entry, 'ATTR'.
+pub const ATTR_MAGIC: u32 = 0x4154_5452;
+/// Offset of the attribute header within the FinderInfo entry, after the FinderInfo and 2 bytes of
+/// padding.
+const ATTR_HEADER_OFFSET: usize = 34;
+const ATTR_HEADER_LEN: usize = 36;
+/// Size of an attribute entry without its name.
+const ATTR_ENTRY_LEN: usize = 11;
+/// Longest attribute name macOS accepts, in bytes and without the terminating NUL.
+const ATTR_MAX_NAME_LEN: usize = 127;
+
+/// Seconds from the Unix epoch to 2000-01-01 00:00:00 UTC, the epoch of the file dates entry.
+const DATE_EPOCH_OFFSET: i64 = 946_684_800;
+
+/// Identifies the contents of an entry.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct EntryId(pub u32);
+
+impl EntryId {
+    pub const DATA_FORK: EntryId = EntryId(1);
+    pub const RESOURCE_FORK: EntryId = EntryId(2);
+    /// The file's name on the system it came from, in Mac OS Roman.
+    pub const REAL_NAME: EntryId = EntryId(3);
+    /// The Finder comment, in Mac OS Roman.
+    pub const COMMENT: EntryId = EntryId(4);
+    pub const ICON_BW: EntryId = EntryId(5);
+    pub const ICON_COLOR: EntryId = EntryId(6);
+    /// Creation, modification, backup and access dates. See `FileDates`.
+    pub const FILE_DATES: EntryId = EntryId(8);
+    /// FinderInfo, followed on macOS by the extended attributes of the file.
+    pub const FINDER_INFO: EntryId = EntryId(9);
+    pub const MACINTOSH_INFO: EntryId = EntryId(10);
+    pub const PRODOS_INFO: EntryId = EntryId(11);
+    pub const MSDOS_INFO: EntryId = EntryId(12);
+    pub const SHORT_NAME: EntryId = EntryId(13);
+    pub const AFP_INFO: EntryId = EntryId(14);
+    pub const DIRECTORY_ID: EntryId = EntryId(15);
+
+    fn is_fork(self) -> bool {
+        self == EntryId::DATA_FORK || self == EntryId::RESOURCE_FORK
+    }
+}
+
+/// A single entry of an AppleDouble file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub id: EntryId,
+    pub data: Vec<u8>,
+}
+
+/// The contents of the file dates entry.
+///
+/// Dates are signed seconds relative to 2000-01-01 00:00:00 UTC; `FileDates::UNKNOWN` marks a date
+/// which isn't known.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FileDates {
+    pub create: i32,
+    pub modify: i32,
+    pub backup: i32,
+    pub access: i32,
+}
+
+impl FileDates {
+    pub const UNKNOWN: i32 = i32::MIN;
+
+    pub fn from_bytes(buf: &[u8; 16]) -> FileDates {
+        let date = |i: usize| i32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        FileDates {
+            create: date(0),
+            modify: date(4),
+            backup: date(8),
+            access: date(12),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut buf = [0u8; 16];
+        buf[0..4].copy_from_slice(&self.create.to_be_bytes());
+        buf[4..8].copy_from_slice(&self.modify.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.backup.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.access.to_be_bytes());
+        buf
+    }
+}
+
+impl Default for FileDates {
+    fn default() -> FileDates {
+        FileDates {
+            create: FileDates::UNKNOWN,
+            modify: FileDates::UNKNOWN,
+            backup: FileDates::UNKNOWN,
+            access: FileDates::UNKNOWN,
+        }
+    }
+}
+
+/// Converts a file dates value to a `SystemTime`, or `None` if it is `FileDates::UNKNOWN`.
+pub fn date_to_system_time(date: i32) -> Option<SystemTime> {
+    if date == FileDates::UNKNOWN {
+        return None;
+    }
+    let secs = i64::from(date) + DATE_EPOCH_OFFSET;
+    Some(if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    })
+}
+
+/// Converts a `SystemTime` to a file dates value, truncating to whole seconds.
+pub fn date_from_system_time(time: SystemTime) -> Result<i32> {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => i64::try_from(d.as_secs()).map_err(|_| Error::DateOutOfRange)?,
+        Err(e) => {
+            let d = e.duration();
+            let whole = i64::try_from(d.as_secs()).map_err(|_| Error::DateOutOfRange)?;
+            // Round towards the past so that times before the epoch truncate consistently.
+            -whole - i64::from(d.subsec_nanos() > 0)
+        }
+    };
+    match i32::try_from(secs - DATE_EPOCH_OFFSET) {
+        Ok(date) if date != FileDates::UNKNOWN => Ok(date),
+        _ => Err(Error::DateOutOfRange),
+    }
+}
+
+/// Returns the path of the AppleDouble sidecar for `path`, i.e. `dir/._name` for `dir/name`.
+///
+/// Returns `None` if `path` has no file name, e.g. `/` or `..`.
+pub fn sidecar_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?;
+    let mut sidecar = OsString::from("._");
+    sidecar.push(name);
+    Some(path.with_file_name(sidecar))
+}
+
+/// An extended attribute from the ATTR block of the FinderInfo entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExtendedAttribute {
+    pub name: String,
+    pub value: Vec<u8>,
+    /// Per-attribute flags. macOS writes 0.
+    pub flags: u16,
+}
+
+impl ExtendedAttribute {
+    pub fn new<N: Into<String>, V: Into<Vec<u8>>>(name: N, value: V) -> ExtendedAttribute {
+        ExtendedAttribute {
+            name: name.into(),
+            value: value.into(),
+            flags: 0,
+        }
+    }
+}
+
+/// Size of an attribute entry whose name, including the NUL, is `name_len` bytes long. Entries are
+/// padded to a multiple of 4 bytes.
+fn attr_entry_len(name_len: usize) -> usize {
+    (ATTR_ENTRY_LEN + name_len + 3) & !3
+}
+
+/// Positions of the fields of an ATTR block, found by walking it without trusting any offsets.
+struct AttrLayout {
+    /// Position of the offset field of each attribute entry, and where its name starts and ends.
+    entries: Vec<(usize, usize, usize)>,
+}
+
+impl AttrLayout {
+    /// Returns `Ok(None)` if the FinderInfo entry has no ATTR block.
+    fn parse(data: &[u8]) -> Result<Option<AttrLayout>> {
+        let header = ATTR_HEADER_OFFSET;
+        if data.len() < header + 4 || be_u32(data, header) != ATTR_MAGIC {
+            return Ok(None);
+        }
+        if data.len() < header + ATTR_HEADER_LEN {
+            return Err(Error::Truncated { offset: data.len() });
+        }
+        let count = usize::from(be_u16(data, header + 34));
+        let mut pos = header + ATTR_HEADER_LEN;
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            let name_len = usize::from(
+                *data
+                    .get(pos + 10)
+                    .ok_or(Error::Truncated { offset: data.len() })?,
+            );
+            let name_start = pos + ATTR_ENTRY_LEN;
+            // The name length includes the terminating NUL.
+            let name_end = name_start + name_len.saturating_sub(1);
+            let next = pos + attr_entry_len(name_len);
+            if data.len() < name_start + name_len {
+                return Err(Error::Truncated { offset: data.len() });
+            }
+            entries.push((pos, name_start, name_end));
+            pos = next;
+        }
+        Ok(Some(AttrLayout { entries }))
+    }
+
+    /// Positions of all the fields which hold file offsets.
+    fn offset_fields(&self) -> impl Iterator<Item = usize> + '_ {
+        let header = ATTR_HEADER_OFFSET;
+        vec![header + 8, header + 12]
+            .into_iter()
+            .chain(self.entries.iter().map(|&(pos, _, _)| pos))
+    }
+}
+
+/// Moves the offsets of the ATTR block in a FinderInfo entry by `delta`. Leaves the entry alone if
+/// it has no well-formed ATTR block.
+///
+/// Fails if an offset would go out of range, e.g. an offset pointing before the entry, as the block
+/// couldn't be moved back when the file is written.
+fn rebase_attributes(data: &mut [u8], delta: i64) -> Result<()> {
+    let layout = match AttrLayout::parse(data) {
+        Ok(Some(layout)) => layout,
+        _ => return Ok(()),
+    };
+    let mut moved = Vec::new();
+    for pos in layout.offset_fields() {
+        match u32::try_from(i64::from(be_u32(data, pos)) + delta) {
+            Ok(value) => moved.push((pos, value)),
+            Err(_) => {
+                return Err(Error::Malformed {
+                    reason: "extended attribute offset is outside the file",
+                })
+            }
+        }
+    }
+    for (pos, value) in moved {
+        data[pos..pos + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    Ok(())
+}
+
+/// Converts a length or offset to a `u32` field, failing if it doesn't fit.
+fn u32_field(field: &'static str, n: usize) -> Result<u32> {
+    u32::try_from(n).map_err(|_| Error::TooLarge {
+        field,
+        max: u32::MAX as usize,
+        actual: n,
+    })
+}
+
+/// Converts a count to a `u16` field, failing if it doesn't fit.
+fn u16_field(field: &'static str, n: usize) -> Result<u16> {
+    u16::try_from(n).map_err(|_| Error::TooLarge {
+        field,
+        max: usize::from(u16::MAX),
+        actual: n,
+    })
+}
+
+/// An AppleDouble file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AppleDouble {
+    pub version: u32,
+    pub filler: [u8; 16],
+    pub entries: Vec<Entry>,
+}
+
+impl Default for AppleDouble {
+    fn default() -> AppleDouble {
+        AppleDouble::new()
+    }
+}
+
+impl AppleDouble {
+    /// Creates an empty version 2 file, as macOS would write it.
+    pub fn new() -> AppleDouble {
+        AppleDouble {
+            version: VERSION_2,
+            filler: MACOS_FILLER,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Reads an AppleDouble file to the end of `r`.
+    pub fn read<R: Read>(r: &mut R) -> Result<AppleDouble> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        AppleDouble::from_slice(&buf)
+    }
+
+    pub fn from_slice(buf: &[u8]) -> Result<AppleDouble> {
+        AppleDouble::parse(buf, MAGIC)
+    }
+
+    /// Parses the header and entries shared by AppleDouble and AppleSingle.
+    pub(crate) fn parse(buf: &[u8], magic: u32) -> Result<AppleDouble> {
+        if buf.len() < HEADER_LEN {
+            return Err(Error::Truncated { offset: buf.len() });
+        }
+        let found = be_u32(buf, 0);
+        if found != magic {
+            return Err(Error::InvalidMagic { found });
+        }
+        let version = be_u32(buf, 4);
+        if version != VERSION_1 && version != VERSION_2 {
+            return Err(Error::UnsupportedVersion { version });
+        }
+        let mut filler = [0u8; 16];
+        filler.copy_from_slice(&buf[8..24]);
+        let count = usize::from(be_u16(buf, 24));
+        let descriptors_end = HEADER_LEN + count * DESCRIPTOR_LEN;
+        if buf.len() < descriptors_end {
+            return Err(Error::Truncated { offset: buf.len() });
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let descriptor = HEADER_LEN + i * DESCRIPTOR_LEN;
+            let id = EntryId(be_u32(buf, descriptor));
+            let offset = be_u32(buf, descriptor + 4) as usize;
+            let length = be_u32(buf, descriptor + 8) as usize;
+            let data = offset
+                .checked_add(length)
+                .and_then(|end| buf.get(offset..end))
+                .ok_or(Error::Truncated { offset: buf.len() })?;
+            let mut data = data.to_vec();
+            if id == EntryId::FINDER_INFO {
+                rebase_attributes(&mut data, -(offset as i64))?;
+            }
+            entries.push(Entry { id, data });
+        }
+        Ok(AppleDouble {
+            version,
+            filler,
+            entries,
+        })
+    }
+
+    /// Serializes the file, laying the entries out back to back after the descriptor table.
+    ///
+    /// Fails with `Error::TooLarge` if there are more than 65535 entries or the file would be
+    /// larger than 4 GiB, which the format can't describe.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.encode(MAGIC)
+    }
+
+    pub(crate) fn encode(&self, magic: u32) -> Result<Vec<u8>> {
+        let data_start = HEADER_LEN + self.entries.len() * DESCRIPTOR_LEN;
+        let data_len: usize = self.entries.iter().map(|e| e.data.len()).sum();
+        let mut buf = Vec::with_capacity(data_start + data_len);
+        buf.extend_from_slice(&magic.to_be_bytes());
+        buf.extend_from_slice(&self.version.to_be_bytes());
+        buf.extend_from_slice(&self.filler);
+        buf.extend_from_slice(&u16_field("entry count", self.entries.len())?.to_be_bytes());
+        let mut offset = data_start;
+        for entry in &self.entries {
+            buf.extend_from_slice(&entry.id.0.to_be_bytes());
+            buf.extend_from_slice(&u32_field("entry offset", offset)?.to_be_bytes());
+            buf.extend_from_slice(&u32_field("entry length", entry.data.len())?.to_be_bytes());
+            offset += entry.data.len();
+        }
+        u32_field("file length", offset)?;
+        for entry in &self.entries {
+            let start = buf.len();
+            buf.extend_from_slice(&entry.data);
+            if entry.id == EntryId::FINDER_INFO {
+                rebase_attributes(&mut buf[start..], start as i64)?;
+            }
+        }
+        Ok(buf)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes()?)?)
+    }
+
+    /// Returns the data of the first entry with the given ID.
+    pub fn entry(&self, id: EntryId) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|e| e.id == id)
+            .map(|e| e.data.as_slice())
+    }
+
+    pub fn entry_mut(&mut self, id: EntryId) -> Option<&mut Vec<u8>> {
+        self.entries
+            .iter_mut()
+            .find(|e| e.id == id)
+            .map(|e| &mut e.data)
+    }
+
+    /// Replaces the data of an entry, or adds the entry if there isn't one.
+    ///
+    /// New entries go before the forks, which macOS keeps at the end of the file so that they can
+    /// grow.
+    pub fn set_entry(&mut self, id: EntryId, data: Vec<u8>) {
+        if let Some(existing) = self.entry_mut(id) {
+            *existing = data;
+            return;
+        }
+        let index = if id.is_fork() {
+            self.entries.len()
+        } else {
+            self.entries
+                .iter()
+                .position(|e| e.id.is_fork())
+                .unwrap_or(self.entries.len())
+        };
+        self.entries.insert(index, Entry { id, data });
+    }
+
+    /// Removes an entry, returning its data.
+    pub fn remove_entry(&mut self, id: EntryId) -> Option<Vec<u8>> {
+        let index = self.entries.iter().position(|e| e.id == id)?;
+        Some(self.entries.remove(index).data)
+    }
+
+    /// Decodes the first 32 bytes of the FinderInfo entry.
+    ///
+    /// Anything after the first 32 bytes is ignored. Returns `Ok(None)` if there is no FinderInfo
+    /// entry and an error if it is shorter than 32 bytes.
+    pub fn finder_info(&self, kind: FinderInfoKind) -> Result<Option<FinderInfo>> {
+        match self.entry(EntryId::FINDER_INFO) {
+            Some(data) => {
+                let data = data
+                    .get(..32)
+                    .ok_or(Error::Truncated { offset: data.len() })?;
+                FinderInfo::from_slice_as(kind, data).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the first 32 bytes of the FinderInfo entry, keeping anything stored after them.
+    pub fn set_finder_info(&mut self, finder_info: &FinderInfo) {
+        let bytes = finder_info.to_bytes();
+        match self.entry_mut(EntryId::FINDER_INFO) {
+            Some(data) if data.len() >= 32 => data[..32].copy_from_slice(&bytes),
+            Some(data) => *data = bytes.to_vec(),
+            None => self.set_entry(EntryId::FINDER_INFO, bytes.to_vec()),
+        }
+    }
+
+    /// Decodes the extended attributes stored after the FinderInfo in the FinderInfo entry.
+    ///
+    /// Returns an empty list if there is no FinderInfo entry or it has no ATTR block.
+    pub fn extended_attributes(&self) -> Result<Vec<ExtendedAttribute>> {
+        let data = match self.entry(EntryId::FINDER_INFO) {
+            Some(data) => data,
+            None => return Ok(Vec::new()),
+        };
+        let layout = match AttrLayout::parse(data)? {
+            Some(layout) => layout,
+            None => return Ok(Vec::new()),
+        };
+        let mut attributes = Vec::with_capacity(layout.entries.len());
+        for &(pos, name_start, name_end) in &layout.entries {
+            let offset = be_u32(data, pos) as usize;
+            let length = be_u32(data, pos + 4) as usize;
+            let value = offset
+                .checked_add(length)
+                .and_then(|end| data.get(offset..end))
+                .ok_or(Error::Truncated { offset: data.len() })?;
+            let name = &data[name_start..name_end];
+            let name = String::from_utf8(name.to_vec()).map_err(|_| Error::Parse {
+                input: String::from_utf8_lossy(name).into_owned(),
+                expected: "a UTF-8 attribute name",
+            })?;
+            attributes.push(ExtendedAttribute {
+                name,
+                value: value.to_vec(),
+                flags: be_u16(data, pos + 8),
+            });
+        }
+        Ok(attributes)
+    }
+
+    /// Rebuilds the ATTR block of the FinderInfo entry from `attributes`, keeping the FinderInfo.
+    ///
+    /// An empty list removes the block. A FinderInfo entry of zeros is added if there isn't one.
+    pub fn set_extended_attributes(&mut self, attributes: &[ExtendedAttribute]) -> Result<()> {
+        let old = self.entry(EntryId::FINDER_INFO).unwrap_or(&[]);
+        let mut data = vec![0u8; 32];
+        let n = old.len().min(32);
+        data[..n].copy_from_slice(&old[..n]);
+        // Keep the debug tag and header flags macOS wrote, if there was a block already.
+        let (debug_tag, flags) = match AttrLayout::parse(old) {
+            Ok(Some(_)) => (
+                be_u32(old, ATTR_HEADER_OFFSET + 4),
+                be_u16(old, ATTR_HEADER_OFFSET + 32),
+            ),
+            _ => (0, 0),
+        };
+        if attributes.is_empty() {
+            self.set_entry(EntryId::FINDER_INFO, data);
+            return Ok(());
+        }
+
+        let mut names = Vec::with_capacity(attributes.len());
+        for attribute in attributes {
+            let name = attribute.name.as_bytes();
+            if name.len() > ATTR_MAX_NAME_LEN || name.contains(&0) {
+                return Err(Error::Parse {
+                    input: attribute.name.clone(),
+                    expected: "an attribute name of at most 127 bytes without NUL",
+                });
+            }
+            names.push(name);
+        }
+        let entries_len: usize = names
+            .iter()
+            .map(|name| attr_entry_len(name.len() + 1))
+            .sum();
+        let data_start = ATTR_HEADER_OFFSET + ATTR_HEADER_LEN + entries_len;
+        let data_length: usize = attributes.iter().map(|a| a.value.len()).sum();
+        let total_size = data_start + data_length;
+
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&ATTR_MAGIC.to_be_bytes());
+        data.extend_from_slice(&debug_tag.to_be_bytes());
+        data.extend_from_slice(
+            &u32_field("attribute header total size", total_size)?.to_be_bytes(),
+        );
+        data.extend_from_slice(&u32_field("attribute data start", data_start)?.to_be_bytes());
+        data.extend_from_slice(&u32_field("attribute data length", data_length)?.to_be_bytes());
+        data.extend_from_slice(&[0u8; 12]);
+        data.extend_from_slice(&flags.to_be_bytes());
+        data.extend_from_slice(&u16_field("attribute count", attributes.len())?.to_be_bytes());
+        let mut offset = data_start;
+        for (attribute, name) in attributes.iter().zip(&names) {
+            let entry_start = data.len();
+            data.extend_from_slice(&u32_field("attribute offset", offset)?.to_be_bytes());
+            data.extend_from_slice(
+                &u32_field("attribute length", attribute.value.len())?.to_be_bytes(),
+            );
+            data.extend_from_slice(&attribute.flags.to_be_bytes());
+            data.push(name.len() as u8 + 1);
+            data.extend_from_slice(name);
+            data.push(0);
+            data.resize(entry_start + attr_entry_len(name.len() + 1), 0);
+            offset += attribute.value.len();
+        }
+        for attribute in attributes {
+            data.extend_from_slice(&attribute.value);
+        }
+        self.set_entry(EntryId::FINDER_INFO, data);
+        Ok(())
+    }
+
+    pub fn resource_fork(&self) -> Option<&[u8]> {
+        self.entry(EntryId::RESOURCE_FORK)
+    }
+
+    pub fn set_resource_fork(&mut self, data: Vec<u8>) {
+        self.set_entry(EntryId::RESOURCE_FORK, data)
+    }
+
+    /// Returns the Finder comment, decoded from Mac OS Roman.
+    pub fn comment(&self) -> Option<String> {
+        self.entry(EntryId::COMMENT).map(macroman::decode)
+    }
+
+    pub fn set_comment(&mut self, comment: &str) -> Result<()> {
+        let data = encode_macroman(comment)?;
+        self.set_entry(EntryId::COMMENT, data);
+        Ok(())
+    }
+
+    /// Returns the original name of the file, decoded from Mac OS Roman.
+    pub fn real_name(&self) -> Option<String> {
+        self.entry(EntryId::REAL_NAME).map(macroman::decode)
+    }
+
+    pub fn set_real_name(&mut self, name: &str) -> Result<()> {
+        let data = encode_macroman(name)?;
+        self.set_entry(EntryId::REAL_NAME, data);
+        Ok(())
+    }
+
+    /// Decodes the file dates entry. Returns an error if it is shorter than 16 bytes.
+    pub fn file_dates(&self) -> Result<Option<FileDates>> {
+        match self.entry(EntryId::FILE_DATES) {
+            Some(data) => {
+                let data = data
+                    .get(..16)
+                    .and_then(|d| <&[u8; 16]>::try_from(d).ok())
+                    .ok_or(Error::Truncated { offset: data.len() })?;
+                Ok(Some(FileDates::from_bytes(data)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_file_dates(&mut self, dates: &FileDates) {
+        self.set_entry(EntryId::FILE_DATES, dates.to_bytes().to_vec())
+    }
+}
+
+fn encode_macroman(s: &str) -> Result<Vec<u8>> {
+    macroman::encode(s).ok_or_else(|| Error::Parse {
+        input: s.to_string(),
+        expected: "text representable in Mac OS Roman",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FinderInfoFile, OSType};
+
+    /// A sidecar in the layout macOS uses: FinderInfo first, then an empty resource fork.
+    fn macos_sidecar() -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&[0x00, 0x05, 0x16, 0x07, 0x00, 0x02, 0x00, 0x00]);
+        buf.extend_from_slice(b"Mac OS X        ");
+        buf.extend_from_slice(&[0x00, 0x02]);
+        buf.extend_from_slice(&[0, 0, 0, 9, 0, 0, 0, 50, 0, 0, 0, 32]);
+        buf.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 82, 0, 0, 0, 4]);
+        buf.extend_from_slice(b"TEXTttxt");
+        buf.extend_from_slice(&[0x04, 0x00]);
+        buf.extend_from_slice(&[0u8; 22]);
+        buf.extend_from_slice(&[1, 2, 3, 4]);
+        buf
+    }
+
+    #[test]
+    fn test_appledouble_roundtrip() {
+        let buf = macos_sidecar();
+        let ad = AppleDouble::from_slice(&buf).unwrap();
+        assert_eq!(ad.version, VERSION_2);
+        assert_eq!(ad.filler, MACOS_FILLER);
+        assert_eq!(ad.resource_fork(), Some(&[1u8, 2, 3, 4][..]));
+        let fi = ad.finder_info(FinderInfoKind::File).unwrap().unwrap();
+        let file = fi.as_file().unwrap();
+        assert_eq!(file.file_info.fileType, OSType::new(b"TEXT"));
+        assert_eq!(file.file_info.fileCreator, OSType::new(b"ttxt"));
+        assert!(file.file_info.finderFlags.has_custom_icon());
+        assert_eq!(ad.to_bytes().unwrap(), buf);
+
+        let mut out = Vec::new();
+        ad.write(&mut out).unwrap();
+        assert_eq!(AppleDouble::read(&mut out.as_slice()).unwrap(), ad);
+    }
+
+    #[test]
+    fn test_appledouble_edit() {
+        let mut ad = AppleDouble::from_slice(&macos_sidecar()).unwrap();
+        ad.set_comment("Résumé").unwrap();
+        assert!(ad.set_real_name("日本").is_err());
+        let dates = FileDates {
+            create: 0,
+            modify: -1,
+            ..FileDates::default()
+        };
+        ad.set_file_dates(&dates);
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(b"PDF ");
+        ad.set_finder_info(&file.into());
+        let ids: Vec<_> = ad.entries.iter().map(|e| e.id).collect();
+        assert_eq!(
+            ids,
+            vec![
+                EntryId::FINDER_INFO,
+                EntryId::COMMENT,
+                EntryId::FILE_DATES,
+                EntryId::RESOURCE_FORK
+            ]
+        );
+
+        let ad = AppleDouble::from_slice(&ad.to_bytes().unwrap()).unwrap();
+        assert_eq!(ad.comment().as_deref(), Some("Résumé"));
+        assert_eq!(ad.entry(EntryId::COMMENT), Some(&b"R\x8esum\x8e"[..]));
+        assert_eq!(ad.file_dates().unwrap(), Some(dates));
+        assert_eq!(ad.real_name(), None);
+        assert_eq!(
+            ad.finder_info(FinderInfoKind::File).unwrap(),
+            Some(FinderInfo::File(file))
+        );
+    }
+
+    #[test]
+    fn test_appledouble_errors() {
+        let buf = macos_sidecar();
+        match AppleDouble::from_slice(&buf[..buf.len() - 1]) {
+            Err(Error::Truncated { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut bad = buf.clone();
+        bad[3] = 0x00;
+        match AppleDouble::from_slice(&bad) {
+            Err(Error::InvalidMagic { found: 0x0005_1600 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut bad = buf;
+        bad[5] = 0x03;
+        match AppleDouble::from_slice(&bad) {
+            Err(Error::UnsupportedVersion {
+                version: 0x0003_0000,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let mut crowded = AppleDouble::new();
+        crowded.entries = vec![
+            Entry {
+                id: EntryId::COMMENT,
+                data: Vec::new(),
+            };
+            0x10000
+        ];
+        match crowded.to_bytes() {
+            Err(Error::TooLarge {
+                field: "entry count",
+                max: 0xffff,
+                actual: 0x10000,
+            }) => {}
+            other => panic!("unexpected result {:?}", other.map(|buf| buf.len())),
+        }
+    }
+
+    #[test]
+    fn test_dates_and_paths() {
+        let epoch = UNIX_EPOCH + Duration::from_secs(946_684_800);
+        assert_eq!(date_to_system_time(0), Some(epoch));
+        assert_eq!(date_to_system_time(FileDates::UNKNOWN), None);
+        assert_eq!(
+            date_to_system_time(-946_684_801),
+            Some(UNIX_EPOCH - Duration::from_secs(1))
+        );
+        assert_eq!(date_from_system_time(epoch).unwrap(), 0);
+        assert_eq!(
+            date_from_system_time(UNIX_EPOCH - Duration::from_millis(500)).unwrap(),
+            -946_684_801
+        );
+        assert!(date_from_system_time(UNIX_EPOCH + Duration::from_secs(1 << 33)).is_err());
+
+        assert_eq!(
+            sidecar_path(Path::new("dir/file.txt")),
+            Some(PathBuf::from("dir/._file.txt"))
+        );
+        assert_eq!(sidecar_path(Path::new("/")), None);
+    }
+
+    #[test]
+    fn test_extended_attributes() {
+        let be = |buf: &[u8], i: usize| be_u32(buf, i) as usize;
+        let quarantine =
+            ExtendedAttribute::new("com.apple.quarantine", &b"0083;5f1e8a00;Safari;"[..]);
+        let where_froms = ExtendedAttribute::new(
+            "com.apple.metadata:kMDItemWhereFroms",
+            &b"bplist00\xa1\x01_\x10\x13https://example.com/"[..],
+        );
+        let attributes = vec![quarantine, where_froms];
+
+        let mut ad = AppleDouble::new();
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(b"TEXT");
+        ad.set_finder_info(&file.into());
+        ad.set_resource_fork(Vec::new());
+        ad.set_extended_attributes(&attributes).unwrap();
+
+        // The FinderInfo entry starts at 50, so the attribute header is at 84 and the entries,
+        // padded to 32 and 48 bytes, start at 120.
+        let buf = ad.to_bytes().unwrap();
+        let values_len = attributes.iter().map(|a| a.value.len()).sum::<usize>();
+        assert_eq!(&buf[84..88], b"ATTR");
+        assert_eq!(be(&buf, 92), 200 + values_len);
+        assert_eq!(be(&buf, 96), 200);
+        assert_eq!(be(&buf, 120), 200);
+        assert_eq!(be(&buf, 152), 200 + attributes[0].value.len());
+        assert_eq!(&buf[200..204], b"0083");
+
+        let mut ad = AppleDouble::from_slice(&buf).unwrap();
+        assert_eq!(ad.extended_attributes().unwrap(), attributes);
+        assert_eq!(
+            ad.finder_info(FinderInfoKind::File).unwrap(),
+            Some(FinderInfo::File(file))
+        );
+        assert_eq!(ad.to_bytes().unwrap(), buf);
+
+        // An offset before the start of the entry can't be made relative to it.
+        let mut bad = buf.clone();
+        bad[120..124].copy_from_slice(&10u32.to_be_bytes());
+        match AppleDouble::from_slice(&bad) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // Another descriptor moves the FinderInfo entry, and with it every offset in the block.
+        ad.set_comment("moved").unwrap();
+        let buf = ad.to_bytes().unwrap();
+        assert_eq!(be(&buf, 108), 212);
+        let mut ad = AppleDouble::from_slice(&buf).unwrap();
+        assert_eq!(ad.extended_attributes().unwrap(), attributes);
+
+        let long = ExtendedAttribute::new("x".repeat(128), Vec::new());
+        assert!(ad.set_extended_attributes(&[long]).is_err());
+        ad.set_extended_attributes(&[]).unwrap();
+        assert_eq!(ad.entry(EntryId::FINDER_INFO).map(|d| d.len()), Some(32));
+        assert_eq!(ad.extended_attributes().unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,135 @@
+//! AppleSingle files, which hold a file's data fork together with its resource fork, FinderInfo
+//! and other metadata in a single self-contained file.
+//!
+//! The format is the same as AppleDouble apart from the magic number and the data fork entry, so
+//! `AppleSingle` wraps an `AppleDouble` and dereferences to it for access to the entries.
+
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+
+use crate::appledouble::{AppleDouble, EntryId, VERSION_2};
+use crate::{FinderInfoFile, FinderInfoKind, Result};
+
+/// Magic number at the start of an AppleSingle file.
+pub const MAGIC: u32 = 0x0005_1600;
+
+/// An AppleSingle file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AppleSingle(pub AppleDouble);
+
+impl Default for AppleSingle {
+    fn default() -> AppleSingle {
+        AppleSingle::new()
+    }
+}
+
+impl AppleSingle {
+    /// Creates an empty version 2 file. Version 2 leaves the filler zeroed.
+    pub fn new() -> AppleSingle {
+        AppleSingle(AppleDouble {
+            version: VERSION_2,
+            filler: [0u8; 16],
+            entries: Vec::new(),
+        })
+    }
+
+    /// Packs a file into an AppleSingle container. The forks are stored last, resource fork first.
+    pub fn pack(
+        finder_info: &FinderInfoFile,
+        data_fork: Vec<u8>,
+        resource_fork: Option<Vec<u8>>,
+    ) -> AppleSingle {
+        let mut single = AppleSingle::new();
+        single.set_finder_info(&(*finder_info).into());
+        if let Some(resource_fork) = resource_fork {
+            single.set_resource_fork(resource_fork);
+        }
+        single.set_data_fork(data_fork);
+        single
+    }
+
+    /// Reads an AppleSingle file to the end of `r`.
+    pub fn read<R: Read>(r: &mut R) -> Result<AppleSingle> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        AppleSingle::from_slice(&buf)
+    }
+
+    pub fn from_slice(buf: &[u8]) -> Result<AppleSingle> {
+        AppleDouble::parse(buf, MAGIC).map(AppleSingle)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.0.encode(MAGIC)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes()?)?)
+    }
+
+    pub fn data_fork(&self) -> Option<&[u8]> {
+        self.entry(EntryId::DATA_FORK)
+    }
+
+    pub fn set_data_fork(&mut self, data: Vec<u8>) {
+        self.set_entry(EntryId::DATA_FORK, data)
+    }
+
+    /// Decodes the FinderInfo entry as file FinderInfo.
+    pub fn finder_info_file(&self) -> Result<Option<FinderInfoFile>> {
+        Ok(self
+            .finder_info(FinderInfoKind::File)?
+            .and_then(|fi| fi.as_file().copied()))
+    }
+}
+
+impl Deref for AppleSingle {
+    type Target = AppleDouble;
+
+    fn deref(&self) -> &AppleDouble {
+        &self.0
+    }
+}
+
+impl DerefMut for AppleSingle {
+    fn deref_mut(&mut self) -> &mut AppleDouble {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, OSType};
+
+    #[test]
+    fn test_applesingle_roundtrip() {
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(b"TEXT");
+        file.file_info.fileCreator = OSType::new(b"ttxt");
+        let single = AppleSingle::pack(&file, b"hello".to_vec(), Some(vec![0xaa; 3]));
+
+        let buf = single.to_bytes().unwrap();
+        assert_eq!(&buf[..8], &[0x00, 0x05, 0x16, 0x00, 0x00, 0x02, 0x00, 0x00]);
+        assert_eq!(&buf[buf.len() - 5..], b"hello");
+
+        let single = AppleSingle::read(&mut buf.as_slice()).unwrap();
+        assert_eq!(single.finder_info_file().unwrap(), Some(file));
+        assert_eq!(single.data_fork(), Some(&b"hello"[..]));
+        assert_eq!(single.resource_fork(), Some(&[0xaa; 3][..]));
+        let ids: Vec<_> = single.entries.iter().map(|e| e.id).collect();
+        assert_eq!(
+            ids,
+            vec![
+                EntryId::FINDER_INFO,
+                EntryId::RESOURCE_FORK,
+                EntryId::DATA_FORK
+            ]
+        );
+
+        match AppleDouble::from_slice(&buf) {
+            Err(Error::InvalidMagic { found: MAGIC }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
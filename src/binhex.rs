@@ -0,0 +1,372 @@
+//! BinHex 4.0 (`.hqx`), the 7-bit text encoding classic Mac files were mailed and posted in.
+//!
+//! The binary stream holds the name, type, creator, Finder flags and fork lengths, followed by the
+//! data fork and the resource fork, each with a CRC. It is run-length encoded with the marker byte
+//! 0x90 and then written six bits to a character, between a pair of colons.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use crate::crc::crc16;
+use crate::{macroman, Error, FinderInfoFile, Result};
+
+/// The line which, by convention, precedes the encoded data.
+pub const BANNER: &str = "(This file must be converted with BinHex 4.0)";
+const ALPHABET: &[u8; 64] = b"!\"#$%&'()*+,-012345689@ABCDEFGHIJKLMNPQRSTUVXYZ[`abcdefhijklmpqr";
+const RUN_MARKER: u8 = 0x90;
+const LINE_LEN: usize = 64;
+const MAX_NAME_LEN: usize = 63;
+
+fn decode_char(c: u8) -> Option<u8> {
+    ALPHABET.iter().position(|&a| a == c).map(|i| i as u8)
+}
+
+/// Decodes the characters between the colons into the run-length encoded stream.
+fn decode_6bit(text: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for &c in text {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        let value = decode_char(c).ok_or(Error::Malformed {
+            reason: "invalid character in BinHex data",
+        })?;
+        bits = (bits << 6) | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn encode_6bit(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() * 4 / 3 + 1);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for &byte in data {
+        bits = (bits << 8) | u32::from(byte);
+        count += 8;
+        while count >= 6 {
+            count -= 6;
+            out.push(ALPHABET[((bits >> count) & 0x3f) as usize]);
+        }
+    }
+    if count > 0 {
+        out.push(ALPHABET[((bits << (6 - count)) & 0x3f) as usize]);
+    }
+    out
+}
+
+/// Expands runs: `c 0x90 n` is `c` repeated `n` times in all, and `0x90 0x00` is a literal 0x90.
+fn decode_rle(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut iter = data.iter();
+    while let Some(&byte) = iter.next() {
+        if byte != RUN_MARKER {
+            out.push(byte);
+            continue;
+        }
+        match iter.next() {
+            Some(0) => out.push(RUN_MARKER),
+            Some(&n) => {
+                let &last = out.last().ok_or(Error::Malformed {
+                    reason: "BinHex run with nothing to repeat",
+                })?;
+                out.resize(out.len() + usize::from(n) - 1, last);
+            }
+            // A marker at the very end can only be padding.
+            None => {}
+        }
+    }
+    Ok(out)
+}
+
+fn encode_rle(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        let run = data[i..]
+            .iter()
+            .take(255)
+            .take_while(|&&b| b == byte)
+            .count();
+        let push_literal = |out: &mut Vec<u8>| {
+            out.push(byte);
+            if byte == RUN_MARKER {
+                out.push(0);
+            }
+        };
+        push_literal(&mut out);
+        if run >= 3 {
+            out.push(RUN_MARKER);
+            out.push(run as u8);
+        } else {
+            for _ in 1..run {
+                push_literal(&mut out);
+            }
+        }
+        i += run;
+    }
+    out
+}
+
+/// Reads the decoded stream, checking each CRC as it goes.
+struct Stream<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Stream<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let part = self
+            .data
+            .get(self.offset..self.offset + len)
+            .ok_or(Error::Truncated {
+                offset: self.data.len(),
+            })?;
+        self.offset += len;
+        Ok(part)
+    }
+
+    fn take_u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes(<[u8; 4]>::try_from(bytes).unwrap()))
+    }
+
+    /// Reads the CRC after the bytes from `start` up to here, and checks it.
+    fn check_crc(&mut self, start: usize) -> Result<()> {
+        let actual = crc16(&self.data[start..self.offset]);
+        let bytes = self.take(2)?;
+        let expected = u16::from_be_bytes([bytes[0], bytes[1]]);
+        if expected != actual {
+            return Err(Error::ChecksumMismatch { expected, actual });
+        }
+        Ok(())
+    }
+}
+
+/// A BinHex 4.0 file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BinHex {
+    /// The file name, decoded from Mac OS Roman.
+    pub name: String,
+    /// Type, creator and Finder flags. BinHex doesn't store the rest.
+    pub finder_info: FinderInfoFile,
+    pub data_fork: Vec<u8>,
+    pub resource_fork: Vec<u8>,
+}
+
+impl BinHex {
+    pub fn new(
+        name: &str,
+        finder_info: FinderInfoFile,
+        data_fork: Vec<u8>,
+        resource_fork: Vec<u8>,
+    ) -> BinHex {
+        BinHex {
+            name: name.to_string(),
+            finder_info,
+            data_fork,
+            resource_fork,
+        }
+    }
+
+    /// Reads BinHex text to the end of `r`.
+    pub fn read<R: Read>(r: &mut R) -> Result<BinHex> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        BinHex::decode(&buf)
+    }
+
+    /// Decodes BinHex text. Anything before the banner line (or, without one, before the first
+    /// line starting with a colon) and after the closing colon is ignored.
+    pub fn decode(text: &[u8]) -> Result<BinHex> {
+        let not_binhex = Error::Malformed {
+            reason: "no BinHex data found",
+        };
+        let start = match find(text, BANNER.as_bytes()) {
+            Some(banner) => {
+                banner
+                    + text[banner..]
+                        .iter()
+                        .position(|&c| c == b':')
+                        .ok_or(not_binhex)?
+            }
+            None => (0..text.len())
+                .find(|&i| {
+                    text[i] == b':' && (i == 0 || text[i - 1] == b'\n' || text[i - 1] == b'\r')
+                })
+                .ok_or(not_binhex)?,
+        } + 1;
+        let end = start
+            + text[start..]
+                .iter()
+                .position(|&c| c == b':')
+                .ok_or(Error::Truncated { offset: text.len() })?;
+        let data = decode_rle(&decode_6bit(&text[start..end])?)?;
+
+        let mut stream = Stream {
+            data: &data,
+            offset: 0,
+        };
+        let name_len = usize::from(stream.take(1)?[0]);
+        let name = macroman::decode(stream.take(name_len)?);
+        let _version = stream.take(1)?;
+        let mut finder_info = FinderInfoFile::default();
+        let info = &mut finder_info.file_info;
+        info.fileType.0.copy_from_slice(stream.take(4)?);
+        info.fileCreator.0.copy_from_slice(stream.take(4)?);
+        let flags = stream.take(2)?;
+        info.finderFlags = u16::from_be_bytes([flags[0], flags[1]]).into();
+        let data_len = stream.take_u32()? as usize;
+        let resource_len = stream.take_u32()? as usize;
+        stream.check_crc(0)?;
+
+        let start = stream.offset;
+        let data_fork = stream.take(data_len)?.to_vec();
+        stream.check_crc(start)?;
+        let start = stream.offset;
+        let resource_fork = stream.take(resource_len)?.to_vec();
+        stream.check_crc(start)?;
+
+        Ok(BinHex {
+            name,
+            finder_info,
+            data_fork,
+            resource_fork,
+        })
+    }
+
+    /// Encodes the file as BinHex text, with the banner and 64 character lines.
+    pub fn encode(&self) -> Result<String> {
+        let name = macroman::encode(&self.name)
+            .filter(|name| !name.is_empty() && name.len() <= MAX_NAME_LEN)
+            .ok_or_else(|| Error::Parse {
+                input: self.name.clone(),
+                expected: "a Mac OS Roman file name of 1 to 63 bytes",
+            })?;
+        let fork_len = |field: &'static str, fork: &[u8]| {
+            u32::try_from(fork.len()).map_err(|_| Error::TooLarge {
+                field,
+                max: u32::MAX as usize,
+                actual: fork.len(),
+            })
+        };
+        let info = &self.finder_info.file_info;
+
+        let mut data = vec![name.len() as u8];
+        data.extend_from_slice(&name);
+        data.push(0);
+        data.extend_from_slice(&info.fileType.0);
+        data.extend_from_slice(&info.fileCreator.0);
+        data.extend_from_slice(&info.finderFlags.bits().to_be_bytes());
+        data.extend_from_slice(&fork_len("data fork length", &self.data_fork)?.to_be_bytes());
+        data.extend_from_slice(
+            &fork_len("resource fork length", &self.resource_fork)?.to_be_bytes(),
+        );
+        let crc = crc16(&data);
+        data.extend_from_slice(&crc.to_be_bytes());
+        for fork in &[&self.data_fork, &self.resource_fork] {
+            data.extend_from_slice(fork);
+            data.extend_from_slice(&crc16(fork).to_be_bytes());
+        }
+
+        let mut body = vec![b':'];
+        body.extend(encode_6bit(&encode_rle(&data)));
+        body.push(b':');
+        let mut text = String::with_capacity(BANNER.len() + body.len() + body.len() / LINE_LEN + 3);
+        text.push_str(BANNER);
+        text.push_str("\n\n");
+        for line in body.chunks(LINE_LEN) {
+            // The alphabet is ASCII.
+            text.push_str(std::str::from_utf8(line).unwrap());
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(self.encode()?.as_bytes())?)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OSType;
+
+    #[test]
+    fn test_rle() {
+        let data = [1, 2, 2, 3, 3, 3, 0x90, 0x90, 0x90, 0x90, 4];
+        let encoded = encode_rle(&data);
+        assert_eq!(encoded, [1, 2, 2, 3, 0x90, 3, 0x90, 0, 0x90, 4, 4]);
+        assert_eq!(decode_rle(&encoded).unwrap(), data);
+        let long = vec![7u8; 600];
+        assert_eq!(decode_rle(&encode_rle(&long)).unwrap(), long);
+        assert!(decode_rle(&[0x90, 3]).is_err());
+    }
+
+    #[test]
+    fn test_binhex_roundtrip() {
+        let mut finder_info = FinderInfoFile::default();
+        finder_info.file_info.fileType = OSType::new(b"TEXT");
+        finder_info.file_info.fileCreator = OSType::new(b"ttxt");
+        finder_info.file_info.finderFlags.set_is_invisible(true);
+        let file = BinHex::new(
+            "Lisez-moi™",
+            finder_info,
+            b"Hello, world!\r".to_vec(),
+            vec![0x90; 300],
+        );
+
+        let text = file.encode().unwrap();
+        assert!(text.starts_with("(This file must be converted with BinHex 4.0)\n\n:"));
+        assert!(text.lines().all(|line| line.len() <= 64));
+        assert!(text.trim_end().ends_with(':'));
+
+        let mut mail = b"From: someone\r\n\r\n".to_vec();
+        mail.extend_from_slice(text.replace('\n', "\r\n").as_bytes());
+        mail.extend_from_slice(b"\r\n-- \r\nsignature\r\n");
+        assert_eq!(BinHex::read(&mut mail.as_slice()).unwrap(), file);
+
+        // The banner is optional.
+        let bare = &text[BANNER.len()..];
+        assert_eq!(BinHex::decode(bare.as_bytes()).unwrap(), file);
+    }
+
+    #[test]
+    fn test_binhex_errors() {
+        let file = BinHex::new("a", FinderInfoFile::default(), b"data".to_vec(), Vec::new());
+        let text = file.encode().unwrap().into_bytes();
+
+        // Flip the low bit of the first byte of the data fork, which follows the 23 byte header.
+        let mut data =
+            decode_rle(&decode_6bit(&text[BANNER.len() + 3..text.len() - 2]).unwrap()).unwrap();
+        data[23] ^= 1;
+        let mut corrupt = b":".to_vec();
+        corrupt.extend(encode_6bit(&encode_rle(&data)));
+        corrupt.push(b':');
+        match BinHex::decode(&corrupt) {
+            Err(Error::ChecksumMismatch { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match BinHex::decode(b"no binhex here") {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match BinHex::decode(&text[..text.len() - 10]) {
+            Err(Error::Truncated { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
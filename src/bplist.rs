@@ -0,0 +1,544 @@
+//! A reader and writer for binary property lists (`bplist00`), the format macOS uses for
+//! extended attributes such as `com.apple.metadata:_kMDItemUserTags`.
+//!
+//! Only the object types found in the wild are supported: booleans, integers, reals, dates,
+//! data, strings, arrays and dictionaries. Sets and UIDs are rejected as malformed. The writer
+//! does not share duplicate objects, which is allowed by the format and keeps the output
+//! predictable. The reader accepts shared scalars, as CoreFoundation writes them, but rejects an
+//! array or dictionary referenced more than once, which CoreFoundation never shares and which
+//! would let a small file expand exponentially when decoded.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use crate::{Error, Result};
+
+const HEADER: &[u8; 8] = b"bplist00";
+const TRAILER_LEN: usize = 32;
+/// Guards against reference cycles and absurdly deep nesting in hostile input.
+const MAX_DEPTH: usize = 64;
+
+/// A value in a property list.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Boolean(bool),
+    Integer(i64),
+    Real(f64),
+    /// Seconds since 2001-01-01 00:00:00 UTC.
+    Date(f64),
+    Data(Vec<u8>),
+    String(String),
+    Array(Vec<Value>),
+    /// Key/value pairs in the order they were read or are to be written.
+    Dictionary(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Looks up `key` in a dictionary.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Dictionary(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Reads a binary property list to the end of `r`.
+    pub fn read<R: Read>(r: &mut R) -> Result<Value> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        Value::from_slice(&buf)
+    }
+
+    pub fn from_slice(buf: &[u8]) -> Result<Value> {
+        if buf.len() < HEADER.len() + TRAILER_LEN {
+            return Err(Error::Truncated { offset: buf.len() });
+        }
+        if &buf[..HEADER.len()] != HEADER {
+            return Err(Error::Malformed {
+                reason: "not a bplist00 binary property list",
+            });
+        }
+        let trailer = &buf[buf.len() - TRAILER_LEN..];
+        let offset_size = usize::from(trailer[6]);
+        let ref_size = usize::from(trailer[7]);
+        let num_objects = be_uint(&trailer[8..16]);
+        let top_object = be_uint(&trailer[16..24]);
+        let table_offset = be_uint(&trailer[24..32]);
+        if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) {
+            return Err(Error::Malformed {
+                reason: "invalid bplist integer size",
+            });
+        }
+        let objects_end = (buf.len() - TRAILER_LEN) as u64;
+        let table_len = num_objects.checked_mul(offset_size as u64);
+        match table_len.and_then(|len| len.checked_add(table_offset)) {
+            Some(end) if table_offset >= HEADER.len() as u64 && end <= objects_end => {}
+            _ => {
+                return Err(Error::Malformed {
+                    reason: "bplist offset table out of bounds",
+                })
+            }
+        }
+        if top_object >= num_objects {
+            return Err(Error::Malformed {
+                reason: "bplist top object out of range",
+            });
+        }
+        let table_offset = table_offset as usize;
+        let table = &buf[table_offset..table_offset + num_objects as usize * offset_size];
+        let mut reader = Reader {
+            buf: &buf[..table_offset],
+            table,
+            offset_size,
+            ref_size,
+            num_objects,
+            containers: HashSet::new(),
+        };
+        reader.object(top_object, 0)
+    }
+
+    /// Encodes the value as a binary property list.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = self.object_count();
+        let ref_size = int_size(count as u64 - 1);
+        let mut writer = Writer {
+            objects: Vec::with_capacity(count),
+            ref_size,
+        };
+        writer.add(self);
+
+        let mut buf = HEADER.to_vec();
+        let mut offsets = Vec::with_capacity(count);
+        for object in &writer.objects {
+            offsets.push(buf.len() as u64);
+            buf.extend_from_slice(object);
+        }
+        let table_offset = buf.len() as u64;
+        let offset_size = int_size(table_offset);
+        for offset in offsets {
+            push_uint(&mut buf, offset, offset_size);
+        }
+        buf.extend_from_slice(&[0u8; 6]);
+        buf.push(offset_size as u8);
+        buf.push(ref_size as u8);
+        buf.extend_from_slice(&(count as u64).to_be_bytes());
+        buf.extend_from_slice(&0u64.to_be_bytes());
+        buf.extend_from_slice(&table_offset.to_be_bytes());
+        buf
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
+
+    fn object_count(&self) -> usize {
+        match self {
+            Value::Array(values) => 1 + values.iter().map(Value::object_count).sum::<usize>(),
+            Value::Dictionary(pairs) => {
+                1 + pairs
+                    .iter()
+                    .map(|(_, v)| 1 + v.object_count())
+                    .sum::<usize>()
+            }
+            _ => 1,
+        }
+    }
+}
+
+fn be_uint(buf: &[u8]) -> u64 {
+    buf.iter().fold(0, |acc, &b| (acc << 8) | u64::from(b))
+}
+
+/// The number of bytes needed to store `n` unsigned.
+fn int_size(n: u64) -> usize {
+    match n {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xffff_ffff => 4,
+        _ => 8,
+    }
+}
+
+fn push_uint(buf: &mut Vec<u8>, n: u64, size: usize) {
+    buf.extend_from_slice(&n.to_be_bytes()[8 - size..]);
+}
+
+struct Reader<'a> {
+    /// Everything before the offset table; objects must lie within it.
+    buf: &'a [u8],
+    table: &'a [u8],
+    offset_size: usize,
+    ref_size: usize,
+    num_objects: u64,
+    /// The arrays and dictionaries decoded so far.
+    containers: HashSet<u64>,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.buf.len() => Ok(&self.buf[offset..end]),
+            _ => Err(Error::Truncated {
+                offset: self.buf.len(),
+            }),
+        }
+    }
+
+    fn object(&mut self, index: u64, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(Error::Malformed {
+                reason: "bplist nested too deeply",
+            });
+        }
+        if index >= self.num_objects {
+            return Err(Error::Malformed {
+                reason: "bplist object reference out of range",
+            });
+        }
+        let start = index as usize * self.offset_size;
+        let offset = be_uint(&self.table[start..start + self.offset_size]);
+        let offset = usize::try_from(offset).map_err(|_| Error::Malformed {
+            reason: "bplist object offset out of range",
+        })?;
+        let marker = self.bytes(offset, 1)?[0];
+        let info = marker & 0x0f;
+        let pos = offset + 1;
+        match marker >> 4 {
+            0x0 => match info {
+                0x8 => Ok(Value::Boolean(false)),
+                0x9 => Ok(Value::Boolean(true)),
+                _ => Err(Error::Malformed {
+                    reason: "unsupported bplist object",
+                }),
+            },
+            0x1 => self.integer(pos, info).map(|(n, _)| Value::Integer(n)),
+            0x2 => self.real(pos, info).map(Value::Real),
+            0x3 if info == 0x3 => self.real(pos, 3).map(Value::Date),
+            0x4 => {
+                let (len, pos) = self.count(pos, info)?;
+                Ok(Value::Data(self.bytes(pos, len)?.to_vec()))
+            }
+            0x5 => {
+                let (len, pos) = self.count(pos, info)?;
+                let bytes = self.bytes(pos, len)?;
+                if !bytes.is_ascii() {
+                    return Err(Error::Malformed {
+                        reason: "bplist ASCII string is not ASCII",
+                    });
+                }
+                Ok(Value::String(
+                    bytes.iter().map(|&b| char::from(b)).collect(),
+                ))
+            }
+            0x6 => {
+                let (len, pos) = self.count(pos, info)?;
+                let len = len.checked_mul(2).ok_or(Error::Malformed {
+                    reason: "bplist string too long",
+                })?;
+                let units: Vec<u16> = self
+                    .bytes(pos, len)?
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16(&units)
+                    .map(Value::String)
+                    .map_err(|_| Error::Malformed {
+                        reason: "bplist string is not valid UTF-16",
+                    })
+            }
+            0xa | 0xd if !self.containers.insert(index) => Err(Error::Malformed {
+                reason: "bplist array or dictionary referenced more than once",
+            }),
+            0xa => {
+                let refs = self.refs(pos, info, 1)?;
+                let mut values = Vec::with_capacity(refs.len());
+                for r in refs {
+                    values.push(self.object(r, depth + 1)?);
+                }
+                Ok(Value::Array(values))
+            }
+            0xd => {
+                let refs = self.refs(pos, info, 2)?;
+                let (keys, values) = refs.split_at(refs.len() / 2);
+                let mut pairs = Vec::with_capacity(keys.len());
+                for (&k, &v) in keys.iter().zip(values) {
+                    let key = match self.object(k, depth + 1)? {
+                        Value::String(key) => key,
+                        _ => {
+                            return Err(Error::Malformed {
+                                reason: "bplist dictionary key is not a string",
+                            })
+                        }
+                    };
+                    pairs.push((key, self.object(v, depth + 1)?));
+                }
+                Ok(Value::Dictionary(pairs))
+            }
+            _ => Err(Error::Malformed {
+                reason: "unsupported bplist object",
+            }),
+        }
+    }
+
+    /// Reads an integer of `1 << info` bytes at `pos`, returning it and the position after it.
+    /// 1, 2 and 4 byte integers are unsigned; 8 byte integers are signed. Of 16 byte integers
+    /// only the low 8 bytes are kept.
+    fn integer(&self, pos: usize, info: u8) -> Result<(i64, usize)> {
+        if info > 4 {
+            return Err(Error::Malformed {
+                reason: "invalid bplist integer size",
+            });
+        }
+        let len = 1usize << info;
+        let bytes = self.bytes(pos, len)?;
+        let n = be_uint(&bytes[len.saturating_sub(8)..]) as i64;
+        Ok((n, pos + len))
+    }
+
+    fn real(&self, pos: usize, info: u8) -> Result<f64> {
+        match info {
+            2 => {
+                let b = self.bytes(pos, 4)?;
+                Ok(f64::from(f32::from_be_bytes([b[0], b[1], b[2], b[3]])))
+            }
+            3 => Ok(f64::from_bits(be_uint(self.bytes(pos, 8)?))),
+            _ => Err(Error::Malformed {
+                reason: "invalid bplist real size",
+            }),
+        }
+    }
+
+    /// Reads the element count of a data, string or collection object, returning it and the
+    /// position of the first element.
+    fn count(&self, pos: usize, info: u8) -> Result<(usize, usize)> {
+        if info != 0x0f {
+            return Ok((usize::from(info), pos));
+        }
+        let marker = self.bytes(pos, 1)?[0];
+        if marker >> 4 != 0x1 {
+            return Err(Error::Malformed {
+                reason: "bplist count is not an integer",
+            });
+        }
+        let (n, next) = self.integer(pos + 1, marker & 0x0f)?;
+        let n = usize::try_from(n).map_err(|_| Error::Malformed {
+            reason: "bplist count out of range",
+        })?;
+        Ok((n, next))
+    }
+
+    fn refs(&self, pos: usize, info: u8, per_element: usize) -> Result<Vec<u64>> {
+        let (count, pos) = self.count(pos, info)?;
+        let len = count
+            .checked_mul(per_element * self.ref_size)
+            .ok_or(Error::Malformed {
+                reason: "bplist collection too large",
+            })?;
+        Ok(self
+            .bytes(pos, len)?
+            .chunks_exact(self.ref_size)
+            .map(be_uint)
+            .collect())
+    }
+}
+
+struct Writer {
+    objects: Vec<Vec<u8>>,
+    ref_size: usize,
+}
+
+impl Writer {
+    /// Appends `value` and everything it contains, returning the index of `value`.
+    fn add(&mut self, value: &Value) -> u64 {
+        let index = self.objects.len();
+        self.objects.push(Vec::new());
+        let mut buf = Vec::new();
+        match value {
+            Value::Boolean(false) => buf.push(0x08),
+            Value::Boolean(true) => buf.push(0x09),
+            Value::Integer(n) => push_integer(&mut buf, *n),
+            Value::Real(r) => {
+                buf.push(0x23);
+                buf.extend_from_slice(&r.to_bits().to_be_bytes());
+            }
+            Value::Date(d) => {
+                buf.push(0x33);
+                buf.extend_from_slice(&d.to_bits().to_be_bytes());
+            }
+            Value::Data(data) => {
+                push_count(&mut buf, 0x4, data.len());
+                buf.extend_from_slice(data);
+            }
+            Value::String(s) => push_string(&mut buf, s),
+            Value::Array(values) => {
+                let refs: Vec<u64> = values.iter().map(|v| self.add(v)).collect();
+                push_count(&mut buf, 0xa, refs.len());
+                self.push_refs(&mut buf, &refs);
+            }
+            Value::Dictionary(pairs) => {
+                let keys: Vec<u64> = pairs
+                    .iter()
+                    .map(|(k, _)| self.add(&Value::String(k.clone())))
+                    .collect();
+                let values: Vec<u64> = pairs.iter().map(|(_, v)| self.add(v)).collect();
+                push_count(&mut buf, 0xd, pairs.len());
+                self.push_refs(&mut buf, &keys);
+                self.push_refs(&mut buf, &values);
+            }
+        }
+        self.objects[index] = buf;
+        index as u64
+    }
+
+    fn push_refs(&self, buf: &mut Vec<u8>, refs: &[u64]) {
+        for &r in refs {
+            push_uint(buf, r, self.ref_size);
+        }
+    }
+}
+
+fn push_integer(buf: &mut Vec<u8>, n: i64) {
+    // Negative numbers are always stored in 8 bytes, as the shorter sizes are unsigned.
+    let size = if n < 0 { 8 } else { int_size(n as u64) };
+    buf.push(0x10 | size.trailing_zeros() as u8);
+    push_uint(buf, n as u64, size);
+}
+
+fn push_count(buf: &mut Vec<u8>, kind: u8, count: usize) {
+    if count < 0x0f {
+        buf.push(kind << 4 | count as u8);
+    } else {
+        buf.push(kind << 4 | 0x0f);
+        push_integer(buf, count as i64);
+    }
+}
+
+fn push_string(buf: &mut Vec<u8>, s: &str) {
+    if s.is_ascii() {
+        push_count(buf, 0x5, s.len());
+        buf.extend_from_slice(s.as_bytes());
+    } else {
+        let units: Vec<u16> = s.encode_utf16().collect();
+        push_count(buf, 0x6, units.len());
+        for unit in units {
+            buf.extend_from_slice(&unit.to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bplist_roundtrip() {
+        let value = Value::Dictionary(vec![
+            (
+                "tags".to_string(),
+                Value::Array(vec![
+                    Value::String("Red\n6".to_string()),
+                    Value::String("Caf\u{e9} \u{1f600}".to_string()),
+                ]),
+            ),
+            ("count".to_string(), Value::Integer(-3)),
+            ("big".to_string(), Value::Integer(0x1_0000_0000)),
+            ("flag".to_string(), Value::Boolean(true)),
+            ("ratio".to_string(), Value::Real(0.5)),
+            ("when".to_string(), Value::Date(600_000_000.0)),
+            ("blob".to_string(), Value::Data(vec![7u8; 20])),
+        ]);
+        let buf = value.to_bytes();
+        assert_eq!(&buf[..8], b"bplist00");
+        let parsed = Value::from_slice(&buf).unwrap();
+        assert_eq!(parsed, value);
+        assert_eq!(parsed.get("count"), Some(&Value::Integer(-3)));
+        assert_eq!(
+            parsed
+                .get("tags")
+                .and_then(Value::as_array)
+                .map(|a| a.len()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_bplist_reference() {
+        // ["Green\n2"], laid out the way Finder writes it.
+        let buf = [
+            0x62, 0x70, 0x6c, 0x69, 0x73, 0x74, 0x30, 0x30, 0xa1, 0x01, 0x57, 0x47, 0x72, 0x65,
+            0x65, 0x6e, 0x0a, 0x32, 0x08, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x12,
+        ];
+        let value = Value::from_slice(&buf).unwrap();
+        assert_eq!(
+            value,
+            Value::Array(vec![Value::String("Green\n2".to_string())])
+        );
+        assert_eq!(value.to_bytes(), &buf[..]);
+    }
+
+    #[test]
+    fn test_bplist_malformed() {
+        match Value::from_slice(b"bplist00") {
+            Err(Error::Truncated { offset: 8 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // An array which contains itself.
+        let mut buf = b"bplist00".to_vec();
+        buf.extend_from_slice(&[0xa1, 0x00, 0x08]);
+        buf.extend_from_slice(&[0u8; 6]);
+        buf.extend_from_slice(&[1, 1]);
+        buf.extend_from_slice(&1u64.to_be_bytes());
+        buf.extend_from_slice(&0u64.to_be_bytes());
+        buf.extend_from_slice(&10u64.to_be_bytes());
+        match Value::from_slice(&buf) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // 64 arrays, each holding two references to the next, which would decode to 2^64 values.
+        let mut buf = b"bplist00".to_vec();
+        let mut offsets = Vec::new();
+        for i in 1..=64u8 {
+            offsets.push(buf.len() as u8);
+            buf.extend_from_slice(&[0xa2, i, i]);
+        }
+        offsets.push(buf.len() as u8);
+        buf.push(0x09);
+        let table_offset = buf.len() as u64;
+        buf.extend_from_slice(&offsets);
+        buf.extend_from_slice(&[0u8; 6]);
+        buf.extend_from_slice(&[1, 1]);
+        buf.extend_from_slice(&(offsets.len() as u64).to_be_bytes());
+        buf.extend_from_slice(&0u64.to_be_bytes());
+        buf.extend_from_slice(&table_offset.to_be_bytes());
+        match Value::from_slice(&buf) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let mut buf = Value::Boolean(true).to_bytes();
+        buf[7] = b'1';
+        match Value::from_slice(&buf) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! The CRC-16 used by MacBinary and BinHex: CCITT polynomial 0x1021, initial value 0, no
+//! reflection, also known as CRC-16/XMODEM.
+
+/// Continues a CRC over `data`.
+pub(crate) fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    for &byte in data {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    crc16_update(0, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(crc16_update(crc16(b"1234"), b"56789"), 0x31c3);
+        assert_eq!(crc16(b""), 0);
+    }
+}
@@ -0,0 +1,98 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Errors returned when reading or writing FinderInfo.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input ended `offset` bytes into a structure that needed more data.
+    Truncated { offset: usize },
+    /// The input had `count` bytes left over after the end of the structure.
+    TrailingBytes { count: usize },
+    /// A value had the wrong length for the structure it was supposed to hold.
+    InvalidLength { expected: usize, actual: usize },
+    /// A value is larger than `max`, the most the format's `field` can hold.
+    TooLarge {
+        field: &'static str,
+        max: usize,
+        actual: usize,
+    },
+    /// A field that is reserved (and should be zero) was not.
+    ReservedField { field: &'static str },
+    /// A container format did not start with the magic number it should have.
+    InvalidMagic { found: u32 },
+    /// A container format had a version this crate doesn't understand.
+    UnsupportedVersion { version: u32 },
+    /// A checksum stored in the input didn't match the data it covers.
+    ChecksumMismatch { expected: u16, actual: u16 },
+    /// The input is not in the format it was expected to be in.
+    Malformed { reason: &'static str },
+    /// A date can't be represented in the format it is being stored in.
+    DateOutOfRange,
+    /// A string could not be parsed.
+    Parse {
+        input: String,
+        expected: &'static str,
+    },
+    /// The underlying reader or writer failed.
+    Io(io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Truncated { offset } => write!(f, "input truncated at byte {}", offset),
+            Error::TrailingBytes { count } => write!(f, "{} unexpected trailing bytes", count),
+            Error::InvalidLength { expected, actual } => {
+                write!(f, "expected {} bytes but found {}", expected, actual)
+            }
+            Error::TooLarge { field, max, actual } => {
+                write!(f, "{} of {} exceeds the maximum of {}", field, actual, max)
+            }
+            Error::ReservedField { field } => write!(f, "reserved field {} is not zero", field),
+            Error::InvalidMagic { found } => write!(f, "invalid magic number {:#010x}", found),
+            Error::UnsupportedVersion { version } => {
+                write!(f, "unsupported version {:#010x}", version)
+            }
+            Error::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {:#06x} but computed {:#06x}",
+                expected, actual
+            ),
+            Error::Malformed { reason } => write!(f, "malformed input: {}", reason),
+            Error::DateOutOfRange => write!(f, "date is out of range"),
+            Error::Parse { input, expected } => {
+                write!(f, "expected {}, found {:?}", expected, input)
+            }
+            Error::Io(e) => write!(f, "i/o error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Io(e) => e,
+            Error::Truncated { .. } => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
@@ -0,0 +1,864 @@
+//! Reading FinderInfo straight out of the catalog of an HFS+ or HFSX volume image.
+//!
+//! In HFS+, FinderInfo isn't an attribute at all: `FileInfo`/`FolderInfo` and their extended
+//! halves are the `userInfo` and `finderInfo` fields of each catalog file and folder record. This
+//! module reads the volume header, follows the catalog file's extents (including any in the
+//! extents overflow file) and walks the leaf nodes of the catalog B-tree.
+//!
+//! Volumes inside an HFS wrapper are found automatically. Partition maps are not parsed; use
+//! `Volume::open_at` with the partition's byte offset. The journal is not replayed, so an image of
+//! a volume which wasn't cleanly unmounted may be missing the latest changes.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{be_u16, be_u32, exact_slice, read_full, Error, FinderInfo, FinderInfoKind, Result};
+
+/// Offset of the volume header from the start of the volume.
+pub const VOLUME_HEADER_OFFSET: u64 = 1024;
+/// `'H+'`.
+pub const SIGNATURE_HFS_PLUS: u16 = 0x482b;
+/// `'HX'`, a case-sensitive HFS+ volume.
+pub const SIGNATURE_HFSX: u16 = 0x4858;
+/// `'BD'`, an HFS volume, which may wrap an HFS+ volume.
+pub const SIGNATURE_HFS: u16 = 0x4244;
+
+/// CNID of the root folder's parent.
+pub const ROOT_PARENT_ID: u32 = 1;
+/// CNID of the root folder.
+pub const ROOT_FOLDER_ID: u32 = 2;
+/// CNID of the extents overflow file.
+pub const EXTENTS_FILE_ID: u32 = 3;
+/// CNID of the catalog file.
+pub const CATALOG_FILE_ID: u32 = 4;
+
+const VOLUME_HEADER_LEN: usize = 512;
+const NODE_DESCRIPTOR_LEN: usize = 14;
+const MIN_NODE_SIZE: usize = 512;
+
+const KIND_LEAF: i8 = -1;
+const KIND_HEADER: i8 = 1;
+
+/// `keyCompareType` of an HFSX catalog with binary, case-sensitive name comparison.
+const BINARY_COMPARE: u8 = 0xbc;
+
+const FOLDER_RECORD: u16 = 1;
+const FILE_RECORD: u16 = 2;
+const FOLDER_THREAD_RECORD: u16 = 3;
+const FOLDER_RECORD_LEN: usize = 88;
+const FILE_RECORD_LEN: usize = 248;
+
+/// The error for offsets computed from the image which don't fit in 64 bits.
+fn out_of_range() -> Error {
+    Error::Malformed {
+        reason: "HFS+ offset is out of range",
+    }
+}
+
+fn be_u64(buf: &[u8], offset: usize) -> u64 {
+    (u64::from(be_u32(buf, offset)) << 32) | u64::from(be_u32(buf, offset + 4))
+}
+
+/// A run of contiguous allocation blocks.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Extent {
+    pub start_block: u32,
+    pub block_count: u32,
+}
+
+/// The size and location of one of the volume's special files.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ForkData {
+    pub logical_size: u64,
+    pub total_blocks: u32,
+    /// The first eight extents. Any more are in the extents overflow file.
+    pub extents: [Extent; 8],
+}
+
+impl ForkData {
+    fn from_bytes(buf: &[u8]) -> ForkData {
+        let mut extents = [Extent::default(); 8];
+        for (i, extent) in extents.iter_mut().enumerate() {
+            *extent = Extent {
+                start_block: be_u32(buf, 16 + i * 8),
+                block_count: be_u32(buf, 20 + i * 8),
+            };
+        }
+        ForkData {
+            logical_size: be_u64(buf, 0),
+            total_blocks: be_u32(buf, 12),
+            extents,
+        }
+    }
+
+    fn inline_blocks(&self) -> u64 {
+        self.extents.iter().map(|e| u64::from(e.block_count)).sum()
+    }
+}
+
+/// The parts of the volume header needed to find the catalog.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VolumeHeader {
+    /// `SIGNATURE_HFS_PLUS` or `SIGNATURE_HFSX`.
+    pub signature: u16,
+    pub version: u16,
+    pub block_size: u32,
+    pub total_blocks: u32,
+    pub file_count: u32,
+    pub folder_count: u32,
+    /// The volume's own FinderInfo, which holds the blessed system folder among other things. It
+    /// is laid out differently from the FinderInfo of files and folders.
+    pub finder_info: [u8; 32],
+    pub extents_file: ForkData,
+    pub catalog_file: ForkData,
+}
+
+impl VolumeHeader {
+    pub fn from_bytes(buf: &[u8; VOLUME_HEADER_LEN]) -> Result<VolumeHeader> {
+        let signature = be_u16(buf, 0);
+        if signature != SIGNATURE_HFS_PLUS && signature != SIGNATURE_HFSX {
+            return Err(Error::InvalidMagic {
+                found: u32::from(signature),
+            });
+        }
+        let version = be_u16(buf, 2);
+        if version != 4 && version != 5 {
+            return Err(Error::UnsupportedVersion {
+                version: u32::from(version),
+            });
+        }
+        let block_size = be_u32(buf, 40);
+        if block_size < 512 || !block_size.is_power_of_two() {
+            return Err(Error::Malformed {
+                reason: "HFS+ block size is not a power of two of at least 512",
+            });
+        }
+        Ok(VolumeHeader {
+            signature,
+            version,
+            block_size,
+            total_blocks: be_u32(buf, 44),
+            file_count: be_u32(buf, 32),
+            folder_count: be_u32(buf, 36),
+            finder_info: *exact_slice::<32>(&buf[80..112])?,
+            extents_file: ForkData::from_bytes(&buf[192..272]),
+            catalog_file: ForkData::from_bytes(&buf[272..352]),
+        })
+    }
+}
+
+/// A file or folder in the catalog.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CatalogEntry {
+    /// The POSIX path from the root of the volume, e.g. `/Documents/Letter`. `None` if a folder
+    /// along the way is missing from the catalog, which happens on damaged volumes.
+    pub path: Option<String>,
+    pub cnid: u32,
+    pub parent_cnid: u32,
+    /// The POSIX name, i.e. with `:` and `/` swapped. Names are in the decomposed form HFS+
+    /// stores them in. The root folder's name is the volume name.
+    pub name: String,
+    /// `FinderInfo::File` or `FinderInfo::Directory`.
+    pub finder_info: FinderInfo,
+}
+
+/// Converts an on-disk name, which uses the classic Mac OS `:` separator and so may contain `/`,
+/// to a POSIX name.
+fn posix_name(units: &[u16]) -> String {
+    String::from_utf16_lossy(units)
+        .chars()
+        .map(|c| match c {
+            '/' => ':',
+            ':' => '/',
+            c => c,
+        })
+        .collect()
+}
+
+/// An open HFS+ or HFSX volume.
+#[derive(Debug)]
+pub struct Volume<R> {
+    reader: R,
+    /// Byte offset of the start of the (possibly embedded) HFS+ volume in `reader`.
+    offset: u64,
+    header: VolumeHeader,
+    catalog_extents: Vec<Extent>,
+    node_size: usize,
+    first_leaf: u32,
+    case_sensitive: bool,
+}
+
+impl<R: Read + Seek> Volume<R> {
+    /// Opens a volume which starts at the beginning of `reader`.
+    pub fn open(reader: R) -> Result<Volume<R>> {
+        Volume::open_at(reader, 0)
+    }
+
+    /// Opens a volume starting `offset` bytes into `reader`, e.g. a partition in a disk image. An
+    /// HFS+ volume embedded in an HFS wrapper is opened instead of the wrapper.
+    pub fn open_at(mut reader: R, offset: u64) -> Result<Volume<R>> {
+        let mut offset = offset;
+        let header_offset = |offset: u64| {
+            offset
+                .checked_add(VOLUME_HEADER_OFFSET)
+                .ok_or_else(out_of_range)
+        };
+        let mut buf = [0u8; VOLUME_HEADER_LEN];
+        read_at(&mut reader, header_offset(offset)?, &mut buf)?;
+        if be_u16(&buf, 0) == SIGNATURE_HFS {
+            offset = offset
+                .checked_add(embedded_volume_offset(&buf)?)
+                .ok_or_else(out_of_range)?;
+            read_at(&mut reader, header_offset(offset)?, &mut buf)?;
+        }
+        let header = VolumeHeader::from_bytes(&buf)?;
+        let mut volume = Volume {
+            reader,
+            offset,
+            catalog_extents: header.catalog_file.extents.to_vec(),
+            header,
+            node_size: MIN_NODE_SIZE,
+            first_leaf: 0,
+            case_sensitive: false,
+        };
+
+        let catalog = &volume.header.catalog_file;
+        if catalog.inline_blocks() < u64::from(catalog.total_blocks) {
+            let more = volume.overflow_extents(CATALOG_FILE_ID)?;
+            volume.catalog_extents.extend(more);
+        }
+
+        let mut node = vec![0u8; MIN_NODE_SIZE];
+        volume.read_catalog(0, &mut node)?;
+        if node[8] as i8 != KIND_HEADER {
+            return Err(Error::Malformed {
+                reason: "HFS+ catalog does not start with a header node",
+            });
+        }
+        let header_record = &node[NODE_DESCRIPTOR_LEN..];
+        volume.first_leaf = be_u32(header_record, 10);
+        volume.node_size = usize::from(be_u16(header_record, 18));
+        volume.case_sensitive =
+            volume.header.signature == SIGNATURE_HFSX && header_record[37] == BINARY_COMPARE;
+        if volume.node_size < MIN_NODE_SIZE || !volume.node_size.is_power_of_two() {
+            return Err(Error::Malformed {
+                reason: "HFS+ catalog node size is not a power of two of at least 512",
+            });
+        }
+        Ok(volume)
+    }
+
+    pub fn header(&self) -> &VolumeHeader {
+        &self.header
+    }
+
+    /// Whether names are compared case-sensitively, as on HFSX volumes created that way.
+    pub fn is_case_sensitive(&self) -> bool {
+        self.case_sensitive
+    }
+
+    /// Reads every file and folder record in the catalog, sorted by path. A folder thread record
+    /// stands in for its folder when building paths if the folder's own record is missing.
+    /// Records which can't be parsed are skipped rather than failing the whole catalog, so a
+    /// damaged volume loses only the affected files and folders.
+    pub fn catalog(&mut self) -> Result<Vec<CatalogEntry>> {
+        let node_size = self.node_size;
+        let mut entries = Vec::new();
+        let mut folders = HashMap::new();
+        let mut node = vec![0u8; node_size];
+        let mut index = self.first_leaf;
+        let mut visited = HashSet::new();
+        while index != 0 {
+            if !visited.insert(index) {
+                return Err(Error::Malformed {
+                    reason: "HFS+ catalog leaf nodes form a cycle",
+                });
+            }
+            self.read_catalog(u64::from(index) * node_size as u64, &mut node)?;
+            if node[8] as i8 != KIND_LEAF {
+                return Err(Error::Malformed {
+                    reason: "HFS+ catalog leaf chain reaches a non-leaf node",
+                });
+            }
+            for record in node_records(&node)? {
+                match parse_catalog_record(record) {
+                    Ok(Some(CatalogRecord::Entry(entry))) => entries.push(entry),
+                    Ok(Some(CatalogRecord::FolderThread {
+                        cnid,
+                        parent_cnid,
+                        name,
+                    })) => {
+                        folders.insert(cnid, (parent_cnid, name));
+                    }
+                    Ok(None) | Err(_) => {}
+                }
+            }
+            index = be_u32(&node, 0);
+        }
+
+        folders.extend(
+            entries
+                .iter()
+                .filter(|e| matches!(e.finder_info, FinderInfo::Directory(_)))
+                .map(|e| (e.cnid, (e.parent_cnid, e.name.clone()))),
+        );
+        for entry in &mut entries {
+            entry.path = build_path(&folders, entry.cnid, entry.parent_cnid, &entry.name);
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path).then(a.cnid.cmp(&b.cnid)));
+        Ok(entries)
+    }
+
+    /// Looks up the entry at the POSIX `path`. `path` must use the decomposed form for accented
+    /// characters.
+    ///
+    /// This is a linear scan over `catalog`, so it reads the whole catalog on every call; call
+    /// `catalog` once instead to look up many paths. On case-insensitive volumes the comparison is
+    /// an approximation: names are folded with `str::to_lowercase` rather than the HFS+ case folding
+    /// table, which differs for some characters and also ignores certain code points. A name that
+    /// only matches under HFS+ rules may not be found.
+    pub fn find(&mut self, path: &str) -> Result<Option<CatalogEntry>> {
+        let case_sensitive = self.case_sensitive;
+        let fold = |s: &str| -> String {
+            if case_sensitive {
+                s.to_string()
+            } else {
+                s.to_lowercase()
+            }
+        };
+        let wanted = fold(path.trim_end_matches('/'));
+        let wanted = if wanted.is_empty() {
+            "/".to_string()
+        } else {
+            wanted
+        };
+        Ok(self
+            .catalog()?
+            .into_iter()
+            .find(|e| e.path.as_deref().map(fold).as_deref() == Some(wanted.as_str())))
+    }
+
+    /// Reads from the catalog file at a logical offset, following its extents.
+    fn read_catalog(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let extents = self.catalog_extents.clone();
+        self.read_fork(&extents, offset, buf)
+    }
+
+    /// Reads from a fork at a logical offset. Every offset is computed with checked arithmetic, as
+    /// the extents and block size come straight from the image.
+    fn read_fork(&mut self, extents: &[Extent], offset: u64, buf: &mut [u8]) -> Result<()> {
+        let block_size = u64::from(self.header.block_size);
+        let mut done = 0;
+        while done < buf.len() {
+            let logical = offset.checked_add(done as u64).ok_or_else(out_of_range)?;
+            let mut extent_start = 0u64;
+            let mut physical = None;
+            for extent in extents {
+                // Both factors are below 2^32, so this can't overflow.
+                let extent_len = u64::from(extent.block_count) * block_size;
+                let extent_end = extent_start
+                    .checked_add(extent_len)
+                    .ok_or_else(out_of_range)?;
+                if logical < extent_end {
+                    let within = logical - extent_start;
+                    let start = (u64::from(extent.start_block) * block_size)
+                        .checked_add(within)
+                        .and_then(|start| start.checked_add(self.offset))
+                        .ok_or_else(out_of_range)?;
+                    physical = Some((start, extent_end - logical));
+                    break;
+                }
+                extent_start = extent_end;
+            }
+            let (start, available) = physical.ok_or(Error::Malformed {
+                reason: "HFS+ read beyond the end of a fork",
+            })?;
+            let len = usize::try_from(available)
+                .unwrap_or(usize::MAX)
+                .min(buf.len() - done);
+            read_at(&mut self.reader, start, &mut buf[done..done + len])?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Collects the extents of the data fork of `file_id` recorded in the extents overflow file.
+    fn overflow_extents(&mut self, file_id: u32) -> Result<Vec<Extent>> {
+        let extents = self.header.extents_file.extents;
+        let mut node = vec![0u8; MIN_NODE_SIZE];
+        self.read_fork(&extents, 0, &mut node)?;
+        let header_record = &node[NODE_DESCRIPTOR_LEN..];
+        let node_size = usize::from(be_u16(header_record, 18));
+        if node_size < MIN_NODE_SIZE {
+            return Err(Error::Malformed {
+                reason: "HFS+ extents node size is too small",
+            });
+        }
+
+        let mut found = Vec::new();
+        let mut node = vec![0u8; node_size];
+        let mut index = be_u32(header_record, 10);
+        let mut visited = HashSet::new();
+        while index != 0 {
+            if !visited.insert(index) {
+                return Err(Error::Malformed {
+                    reason: "HFS+ extents leaf nodes form a cycle",
+                });
+            }
+            self.read_fork(&extents, u64::from(index) * node_size as u64, &mut node)?;
+            if node[8] as i8 != KIND_LEAF {
+                return Err(Error::Malformed {
+                    reason: "HFS+ extents leaf chain reaches a non-leaf node",
+                });
+            }
+            for record in node_records(&node)? {
+                // keyLength, forkType, pad, fileID, startBlock, then eight extents.
+                if record.len() < 12 + 64 {
+                    return Err(Error::Truncated {
+                        offset: record.len(),
+                    });
+                }
+                if record[2] != 0 || be_u32(record, 4) != file_id {
+                    continue;
+                }
+                let start_block = be_u32(record, 8);
+                let data = ForkData::from_bytes(&[&[0u8; 16][..], &record[12..76]].concat());
+                found.push((start_block, data.extents));
+            }
+            index = be_u32(&node, 0);
+        }
+        found.sort_by_key(|&(start_block, _)| start_block);
+        Ok(found
+            .into_iter()
+            .flat_map(|(_, extents)| extents.to_vec())
+            .filter(|e| e.block_count != 0)
+            .collect())
+    }
+}
+
+fn read_at<R: Read + Seek>(reader: &mut R, offset: u64, buf: &mut [u8]) -> Result<()> {
+    reader.seek(SeekFrom::Start(offset))?;
+    read_full(reader, buf)
+}
+
+/// The byte offset of an HFS+ volume embedded in the HFS master directory block `mdb`.
+fn embedded_volume_offset(mdb: &[u8]) -> Result<u64> {
+    let embedded_signature = be_u16(mdb, 0x7c);
+    if embedded_signature != SIGNATURE_HFS_PLUS {
+        return Err(Error::Malformed {
+            reason: "plain HFS volumes are not supported",
+        });
+    }
+    let allocation_block_size = u64::from(be_u32(mdb, 0x14));
+    let first_allocation_block = u64::from(be_u16(mdb, 0x1c));
+    let embedded_start = u64::from(be_u16(mdb, 0x7e));
+    Ok(first_allocation_block * 512 + embedded_start * allocation_block_size)
+}
+
+/// Splits a B-tree node into its records using the offsets at the end of the node.
+fn node_records(node: &[u8]) -> Result<Vec<&[u8]>> {
+    let count = usize::from(be_u16(node, 10));
+    let table = node
+        .len()
+        .checked_sub(2 * (count + 1))
+        .ok_or(Error::Malformed {
+            reason: "HFS+ node has too many records",
+        })?;
+    let offset = |i: usize| usize::from(be_u16(node, node.len() - 2 * (i + 1)));
+    let mut records = Vec::with_capacity(count);
+    for i in 0..count {
+        let (start, end) = (offset(i), offset(i + 1));
+        // The last offset points at free space, which ends where the offset table starts.
+        if start < NODE_DESCRIPTOR_LEN || end < start || end > table {
+            return Err(Error::Malformed {
+                reason: "HFS+ node record offsets are out of order",
+            });
+        }
+        records.push(&node[start..end]);
+    }
+    Ok(records)
+}
+
+/// The catalog leaf records this module uses.
+enum CatalogRecord {
+    Entry(CatalogEntry),
+    /// A folder thread record, which gives the parent and name of the folder `cnid`.
+    FolderThread {
+        cnid: u32,
+        parent_cnid: u32,
+        name: String,
+    },
+}
+
+/// Reads the big-endian UTF-16 name with a 16 bit length at the start of `buf`.
+fn name_units(buf: &[u8]) -> Result<Vec<u16>> {
+    let len = usize::from(be_u16(buf, 0));
+    match buf.get(2..2 + 2 * len) {
+        Some(bytes) => Ok(bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect()),
+        None => Err(Error::Truncated { offset: buf.len() }),
+    }
+}
+
+/// Parses a catalog leaf record, returning `None` for file thread records and any record type
+/// this module doesn't know.
+fn parse_catalog_record(record: &[u8]) -> Result<Option<CatalogRecord>> {
+    if record.len() < 8 {
+        return Err(Error::Truncated {
+            offset: record.len(),
+        });
+    }
+    let key_len = usize::from(be_u16(record, 0));
+    let parent_cnid = be_u32(record, 2);
+    let name_len = usize::from(be_u16(record, 6));
+    let data_start = 2 + key_len;
+    if key_len < 6 + 2 * name_len || record.len() < data_start + 2 {
+        return Err(Error::Truncated {
+            offset: record.len(),
+        });
+    }
+    let units = name_units(&record[6..])?;
+    let data = &record[data_start..];
+    let (kind, len) = match be_u16(data, 0) {
+        FOLDER_RECORD => (FinderInfoKind::Directory, FOLDER_RECORD_LEN),
+        FILE_RECORD => (FinderInfoKind::File, FILE_RECORD_LEN),
+        // recordType, reserved, parentID, then the folder's name. The key holds the folder's CNID.
+        FOLDER_THREAD_RECORD if data.len() >= 10 => {
+            return Ok(Some(CatalogRecord::FolderThread {
+                cnid: parent_cnid,
+                parent_cnid: be_u32(data, 4),
+                name: posix_name(&name_units(&data[8..])?),
+            }))
+        }
+        FOLDER_THREAD_RECORD => return Err(Error::Truncated { offset: data.len() }),
+        _ => return Ok(None),
+    };
+    if data.len() < len {
+        return Err(Error::Truncated { offset: data.len() });
+    }
+    Ok(Some(CatalogRecord::Entry(CatalogEntry {
+        path: None,
+        cnid: be_u32(data, 8),
+        parent_cnid,
+        name: posix_name(&units),
+        finder_info: FinderInfo::from_bytes_as(kind, exact_slice::<32>(&data[48..80])?),
+    })))
+}
+
+fn build_path(
+    folders: &HashMap<u32, (u32, String)>,
+    cnid: u32,
+    parent_cnid: u32,
+    name: &str,
+) -> Option<String> {
+    if cnid == ROOT_FOLDER_ID {
+        return Some("/".to_string());
+    }
+    let mut components = vec![name];
+    let mut parent = parent_cnid;
+    while parent != ROOT_FOLDER_ID {
+        // Folders can't be nested deeper than there are folders.
+        if components.len() > folders.len() {
+            return None;
+        }
+        let (grandparent, name) = folders.get(&parent)?;
+        components.push(name);
+        parent = *grandparent;
+    }
+    components.reverse();
+    Some(format!("/{}", components.join("/")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FinderInfoFile, FinderInfoFolder, OSType};
+    use std::io::Cursor;
+
+    const BLOCK: usize = 1024;
+
+    fn key(parent: u32, name: &str) -> Vec<u8> {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&((6 + 2 * units.len()) as u16).to_be_bytes());
+        buf.extend_from_slice(&parent.to_be_bytes());
+        buf.extend_from_slice(&(units.len() as u16).to_be_bytes());
+        for unit in units {
+            buf.extend_from_slice(&unit.to_be_bytes());
+        }
+        buf
+    }
+
+    fn folder(parent: u32, name: &str, cnid: u32, fi: &FinderInfoFolder) -> Vec<u8> {
+        let mut record = key(parent, name);
+        let mut data = vec![0u8; FOLDER_RECORD_LEN];
+        data[..2].copy_from_slice(&FOLDER_RECORD.to_be_bytes());
+        data[8..12].copy_from_slice(&cnid.to_be_bytes());
+        data[48..80].copy_from_slice(&fi.to_bytes());
+        record.extend(data);
+        record
+    }
+
+    fn file(parent: u32, name: &str, cnid: u32, fi: &FinderInfoFile) -> Vec<u8> {
+        let mut record = key(parent, name);
+        let mut data = vec![0u8; FILE_RECORD_LEN];
+        data[..2].copy_from_slice(&FILE_RECORD.to_be_bytes());
+        data[8..12].copy_from_slice(&cnid.to_be_bytes());
+        data[48..80].copy_from_slice(&fi.to_bytes());
+        record.extend(data);
+        record
+    }
+
+    fn thread(cnid: u32, parent: u32, name: &str) -> Vec<u8> {
+        let mut record = key(cnid, "");
+        record.extend_from_slice(&3u16.to_be_bytes());
+        record.extend_from_slice(&[0u8; 2]);
+        record.extend_from_slice(&parent.to_be_bytes());
+        record.extend(key(0, name)[6..].to_vec());
+        record
+    }
+
+    fn node(kind: i8, next: u32, records: &[Vec<u8>], size: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; size];
+        buf[0..4].copy_from_slice(&next.to_be_bytes());
+        buf[8] = kind as u8;
+        buf[10..12].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = NODE_DESCRIPTOR_LEN;
+        for (i, record) in records.iter().enumerate() {
+            buf[offset..offset + record.len()].copy_from_slice(record);
+            buf[size - 2 * (i + 1)..size - 2 * i].copy_from_slice(&(offset as u16).to_be_bytes());
+            offset += record.len();
+        }
+        let i = records.len();
+        buf[size - 2 * (i + 1)..size - 2 * i].copy_from_slice(&(offset as u16).to_be_bytes());
+        buf
+    }
+
+    fn header_node(first_leaf: u32, total_nodes: u32, compare: u8, size: usize) -> Vec<u8> {
+        let mut record = vec![0u8; 106];
+        record[10..14].copy_from_slice(&first_leaf.to_be_bytes());
+        record[18..20].copy_from_slice(&(size as u16).to_be_bytes());
+        record[22..26].copy_from_slice(&total_nodes.to_be_bytes());
+        record[37] = compare;
+        node(KIND_HEADER, 0, &[record], size)
+    }
+
+    fn put_fork(image: &mut [u8], at: usize, extents: &[(u32, u32)]) {
+        let total: u32 = extents.iter().map(|e| e.1).sum();
+        image[at..at + 8].copy_from_slice(&(u64::from(total) * BLOCK as u64).to_be_bytes());
+        image[at + 12..at + 16].copy_from_slice(&total.to_be_bytes());
+        for (i, &(start, count)) in extents.iter().take(8).enumerate() {
+            image[at + 16 + i * 8..at + 20 + i * 8].copy_from_slice(&start.to_be_bytes());
+            image[at + 20 + i * 8..at + 24 + i * 8].copy_from_slice(&count.to_be_bytes());
+        }
+    }
+
+    /// Builds a volume with 1 KiB blocks. The catalog has 1 KiB nodes: a header node and two
+    /// leaves. With `fragmented`, the catalog is split into nine one-node extents so that the last
+    /// one is only in the extents overflow file.
+    fn build_image(signature: u16, compare: u8, fragmented: bool) -> Vec<u8> {
+        let mut image = vec![0u8; 64 * BLOCK];
+        let vh = VOLUME_HEADER_OFFSET as usize;
+        image[vh..vh + 2].copy_from_slice(&signature.to_be_bytes());
+        let version: u16 = if signature == SIGNATURE_HFSX { 5 } else { 4 };
+        image[vh + 2..vh + 4].copy_from_slice(&version.to_be_bytes());
+        image[vh + 40..vh + 44].copy_from_slice(&(BLOCK as u32).to_be_bytes());
+        image[vh + 44..vh + 48].copy_from_slice(&64u32.to_be_bytes());
+
+        let mut root = FinderInfoFolder::default();
+        root.folder_info.finderFlags.set_is_on_desk(true);
+        let docs = FinderInfoFolder::default();
+        let mut text = FinderInfoFile::default();
+        text.file_info.fileType = OSType::new(b"TEXT");
+        text.file_info.fileCreator = OSType::new(b"ttxt");
+        let mut app = FinderInfoFile::default();
+        app.file_info.fileType = OSType::new(b"APPL");
+
+        let leaf1 = node(
+            KIND_LEAF,
+            10,
+            &[
+                folder(ROOT_PARENT_ID, "Test Volume", ROOT_FOLDER_ID, &root),
+                thread(ROOT_FOLDER_ID, ROOT_PARENT_ID, "Test Volume"),
+                folder(ROOT_FOLDER_ID, "Docs", 16, &docs),
+                file(ROOT_FOLDER_ID, "a/b", 17, &app),
+            ],
+            BLOCK,
+        );
+        let leaf2 = node(
+            KIND_LEAF,
+            0,
+            &[
+                thread(16, ROOT_FOLDER_ID, "Docs"),
+                file(16, "Cafe\u{301}", 18, &text),
+            ],
+            BLOCK,
+        );
+
+        // Catalog node n lives in block 8 + n, except that fragmented catalogs have 11 nodes, one
+        // per extent, with the two leaves last.
+        let total_nodes = if fragmented { 11 } else { 3 };
+        let leaf_index = total_nodes - 2;
+        let mut nodes = vec![vec![0u8; BLOCK]; total_nodes as usize];
+        nodes[0] = header_node(leaf_index, total_nodes, compare, BLOCK);
+        let mut leaf1 = leaf1;
+        leaf1[0..4].copy_from_slice(&(leaf_index + 1).to_be_bytes());
+        nodes[leaf_index as usize] = leaf1;
+        nodes[leaf_index as usize + 1] = leaf2;
+        // Every other block, so that no two extents can be merged.
+        let blocks: Vec<u32> = (0..total_nodes).map(|n| 8 + 2 * n).collect();
+        for (n, node) in nodes.iter().enumerate() {
+            let at = blocks[n] as usize * BLOCK;
+            image[at..at + BLOCK].copy_from_slice(node);
+        }
+        let extents: Vec<(u32, u32)> = blocks.iter().map(|&b| (b, 1)).collect();
+        put_fork(&mut image, vh + 272, &extents);
+
+        if fragmented {
+            // Extents overflow file with one node of records in block 40, header in block 39.
+            let mut record = vec![0u8; 12 + 64];
+            record[0..2].copy_from_slice(&10u16.to_be_bytes());
+            record[4..8].copy_from_slice(&CATALOG_FILE_ID.to_be_bytes());
+            record[8..12].copy_from_slice(&8u32.to_be_bytes());
+            for (i, &(start, count)) in extents[8..].iter().enumerate() {
+                record[12 + i * 8..16 + i * 8].copy_from_slice(&start.to_be_bytes());
+                record[16 + i * 8..20 + i * 8].copy_from_slice(&count.to_be_bytes());
+            }
+            let header = header_node(1, 2, 0, BLOCK);
+            let leaf = node(KIND_LEAF, 0, &[record], BLOCK);
+            image[39 * BLOCK..40 * BLOCK].copy_from_slice(&header);
+            image[40 * BLOCK..41 * BLOCK].copy_from_slice(&leaf);
+            put_fork(&mut image, vh + 192, &[(39, 2)]);
+        }
+        image
+    }
+
+    #[test]
+    fn test_hfsplus_catalog() {
+        for &fragmented in &[false, true] {
+            let image = build_image(SIGNATURE_HFS_PLUS, 0xcf, fragmented);
+            let mut volume = Volume::open(Cursor::new(image)).unwrap();
+            assert!(!volume.is_case_sensitive());
+            assert_eq!(volume.header().block_size, 1024);
+            let entries = volume.catalog().unwrap();
+            let paths: Vec<_> = entries.iter().map(|e| e.path.as_deref().unwrap()).collect();
+            assert_eq!(paths, vec!["/", "/Docs", "/Docs/Cafe\u{301}", "/a:b"]);
+
+            let root = &entries[0];
+            assert_eq!((root.cnid, root.name.as_str()), (2, "Test Volume"));
+            match root.finder_info {
+                FinderInfo::Directory(fi) => assert!(fi.folder_info.finderFlags.is_on_desk()),
+                ref other => panic!("unexpected FinderInfo {:?}", other),
+            }
+            match entries[2].finder_info {
+                FinderInfo::File(fi) => {
+                    assert_eq!(fi.file_info.fileType, OSType::new(b"TEXT"));
+                    assert_eq!(fi.file_info.fileCreator, OSType::new(b"ttxt"));
+                }
+                ref other => panic!("unexpected FinderInfo {:?}", other),
+            }
+            assert_eq!((entries[2].cnid, entries[2].parent_cnid), (18, 16));
+            assert_eq!(entries[3].name, "a:b");
+
+            let found = volume.find("/DOCS/cafe\u{301}").unwrap().unwrap();
+            assert_eq!(found.cnid, 18);
+            assert_eq!(volume.find("/").unwrap().unwrap().cnid, 2);
+            assert!(volume.find("/missing").unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_hfsx_and_wrapper() {
+        let image = build_image(SIGNATURE_HFSX, BINARY_COMPARE, false);
+        let mut volume = Volume::open(Cursor::new(image.clone())).unwrap();
+        assert!(volume.is_case_sensitive());
+        assert!(volume.find("/docs").unwrap().is_none());
+        assert_eq!(volume.find("/Docs").unwrap().unwrap().cnid, 16);
+
+        // The same volume embedded in an HFS wrapper with 4 KiB allocation blocks, starting at
+        // allocation block 2 after 16 sectors.
+        let embedded_at = 16 * 512 + 2 * 4096;
+        let mut wrapped = vec![0u8; embedded_at];
+        wrapped[1024..1026].copy_from_slice(&SIGNATURE_HFS.to_be_bytes());
+        wrapped[1024 + 0x14..1024 + 0x18].copy_from_slice(&4096u32.to_be_bytes());
+        wrapped[1024 + 0x1c..1024 + 0x1e].copy_from_slice(&16u16.to_be_bytes());
+        wrapped[1024 + 0x7c..1024 + 0x7e].copy_from_slice(&SIGNATURE_HFS_PLUS.to_be_bytes());
+        wrapped[1024 + 0x7e..1024 + 0x80].copy_from_slice(&2u16.to_be_bytes());
+        wrapped.extend_from_slice(&image);
+        let mut volume = Volume::open_at(Cursor::new(wrapped), 0).unwrap();
+        assert_eq!(volume.catalog().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_hfsplus_damaged_record() {
+        // Zero the key length of the "Docs" folder record, the third record of the first leaf.
+        let mut image = build_image(SIGNATURE_HFS_PLUS, 0xcf, false);
+        let leaf1 = (8 + 2) * BLOCK;
+        let record = leaf1 + usize::from(be_u16(&image, leaf1 + BLOCK - 6));
+        image[record..record + 2].copy_from_slice(&[0, 0]);
+        let mut volume = Volume::open(Cursor::new(image)).unwrap();
+        let entries = volume.catalog().unwrap();
+        let paths: Vec<_> = entries.iter().map(|e| e.path.as_deref().unwrap()).collect();
+        // The folder is gone, but its thread record still names it.
+        assert_eq!(paths, vec!["/", "/Docs/Cafe\u{301}", "/a:b"]);
+    }
+
+    #[test]
+    fn test_hfsplus_errors() {
+        match Volume::open(Cursor::new(vec![0u8; 4096])) {
+            Err(Error::InvalidMagic { found: 0 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match Volume::open(Cursor::new(vec![0u8; 1000])) {
+            Err(Error::Truncated { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // The second leaf points back at the first, and the header claims there are 2^32 - 1 nodes,
+        // so the node count can't be used to stop the walk.
+        let image = build_image(SIGNATURE_HFS_PLUS, 0xcf, false);
+        let mut cyclic = image.clone();
+        let leaf2 = (8 + 2 * 2) * BLOCK;
+        cyclic[leaf2..leaf2 + 4].copy_from_slice(&1u32.to_be_bytes());
+        let total_nodes = 8 * BLOCK + NODE_DESCRIPTOR_LEN + 22;
+        cyclic[total_nodes..total_nodes + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let mut volume = Volume::open(Cursor::new(cyclic)).unwrap();
+        match volume.catalog() {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // Offsets that would overflow fail instead of panicking.
+        match Volume::open_at(Cursor::new(image.clone()), u64::MAX - 100) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut volume = Volume::open(Cursor::new(image)).unwrap();
+        volume.header.block_size = 0x8000_0000;
+        volume.offset = u64::MAX - 0x4000_0000;
+        match volume.catalog() {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // The extents overflow file's leaf chain runs into an index node.
+        let mut image = build_image(SIGNATURE_HFS_PLUS, 0xcf, true);
+        image[40 * BLOCK + 8] = 0;
+        match Volume::open(Cursor::new(image)) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // A record starting inside the offset table.
+        let mut node = vec![0u8; 512];
+        node[10..12].copy_from_slice(&1u16.to_be_bytes());
+        node[508..512].copy_from_slice(&[0x01, 0xfe, 0x01, 0xfe]);
+        match node_records(&node) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,548 @@
+//! Custom icons, as flagged by `kHasCustomIcon`.
+//!
+//! A custom icon is stored as resources with ID `CUSTOM_ICON_ID` in the resource fork of the file,
+//! or for a folder in the resource fork of the invisible `Icon\r` file inside it. Classic Mac OS
+//! used one resource per size and depth ('ICN#', 'icl8', 'ics4', ...); Mac OS 8.5 and later also
+//! write a single 'icns' resource holding a whole icon family.
+//!
+//! The classic bitmap formats and the RGB formats of `icns` are decoded to RGBA. Newer `icns`
+//! elements which are stored as PNG or JPEG 2000 are returned as they are.
+
+use crate::resource_fork::ResourceFork;
+use crate::{be_u32, Error, FinderFlags, FinderInfo, OSType, Result};
+
+/// The resource ID of a custom icon, `kCustomIconResource`.
+pub const CUSTOM_ICON_ID: i16 = -16455;
+/// The name of the file holding the custom icon of a folder.
+pub const FOLDER_ICON_FILE_NAME: &str = "Icon\r";
+
+const ICNS: OSType = OSType::new(b"icns");
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG2000_SIGNATURE: &[u8] = b"\x00\x00\x00\x0cjP  ";
+
+/// How an icon element is stored.
+#[derive(Clone, Copy)]
+enum Format {
+    /// 1-bit icon followed by a 1-bit mask.
+    Mono,
+    /// 4-bit indexed, masked by the mono icon of the same size.
+    Indexed4,
+    /// 8-bit indexed, masked by the mono icon of the same size.
+    Indexed8,
+    /// Run-length encoded red, green and blue planes, masked by an 8-bit mask element.
+    Rgb,
+    /// 8-bit alpha.
+    Mask,
+    /// "ARGB" followed by run-length encoded alpha, red, green and blue planes.
+    Argb,
+    /// PNG or JPEG 2000 only.
+    Encoded,
+}
+
+/// An element type of an icon family.
+struct Element {
+    code: OSType,
+    width: u32,
+    height: u32,
+    format: Format,
+    /// The element holding the mask to apply.
+    mask: Option<OSType>,
+}
+
+const fn element(
+    code: &[u8; 4],
+    width: u32,
+    height: u32,
+    format: Format,
+    mask: Option<&[u8; 4]>,
+) -> Element {
+    Element {
+        code: OSType::new(code),
+        width,
+        height,
+        format,
+        mask: match mask {
+            Some(mask) => Some(OSType::new(mask)),
+            None => None,
+        },
+    }
+}
+
+/// Known element types. Among elements of the same size, the better ones come later.
+const ELEMENTS: &[Element] = &[
+    element(b"icm#", 16, 12, Format::Mono, None),
+    element(b"ics#", 16, 16, Format::Mono, None),
+    element(b"ICN#", 32, 32, Format::Mono, None),
+    element(b"ich#", 48, 48, Format::Mono, None),
+    element(b"ics4", 16, 16, Format::Indexed4, Some(b"ics#")),
+    element(b"icl4", 32, 32, Format::Indexed4, Some(b"ICN#")),
+    element(b"ich4", 48, 48, Format::Indexed4, Some(b"ich#")),
+    element(b"ics8", 16, 16, Format::Indexed8, Some(b"ics#")),
+    element(b"icl8", 32, 32, Format::Indexed8, Some(b"ICN#")),
+    element(b"ich8", 48, 48, Format::Indexed8, Some(b"ich#")),
+    element(b"is32", 16, 16, Format::Rgb, Some(b"s8mk")),
+    element(b"il32", 32, 32, Format::Rgb, Some(b"l8mk")),
+    element(b"ih32", 48, 48, Format::Rgb, Some(b"h8mk")),
+    element(b"it32", 128, 128, Format::Rgb, Some(b"t8mk")),
+    element(b"s8mk", 16, 16, Format::Mask, None),
+    element(b"l8mk", 32, 32, Format::Mask, None),
+    element(b"h8mk", 48, 48, Format::Mask, None),
+    element(b"t8mk", 128, 128, Format::Mask, None),
+    element(b"ic04", 16, 16, Format::Argb, None),
+    element(b"ic05", 32, 32, Format::Argb, None),
+    element(b"icp4", 16, 16, Format::Rgb, None),
+    element(b"icp5", 32, 32, Format::Rgb, None),
+    element(b"icp6", 64, 64, Format::Encoded, None),
+    element(b"ic11", 32, 32, Format::Encoded, None),
+    element(b"ic12", 64, 64, Format::Encoded, None),
+    element(b"ic07", 128, 128, Format::Encoded, None),
+    element(b"ic08", 256, 256, Format::Encoded, None),
+    element(b"ic13", 256, 256, Format::Encoded, None),
+    element(b"ic09", 512, 512, Format::Encoded, None),
+    element(b"ic14", 512, 512, Format::Encoded, None),
+    element(b"ic10", 1024, 1024, Format::Encoded, None),
+];
+
+/// The standard 16 color palette of classic Mac OS.
+const PALETTE_4: [[u8; 3]; 16] = [
+    [0xff, 0xff, 0xff],
+    [0xfc, 0xf3, 0x05],
+    [0xff, 0x64, 0x02],
+    [0xdd, 0x08, 0x06],
+    [0xf2, 0x08, 0x84],
+    [0x46, 0x00, 0xa5],
+    [0x00, 0x00, 0xd4],
+    [0x02, 0xab, 0xea],
+    [0x1f, 0xb7, 0x14],
+    [0x00, 0x64, 0x11],
+    [0x56, 0x2c, 0x05],
+    [0x90, 0x71, 0x3a],
+    [0xc0, 0xc0, 0xc0],
+    [0x80, 0x80, 0x80],
+    [0x40, 0x40, 0x40],
+    [0x00, 0x00, 0x00],
+];
+
+/// Returns a color of the standard 256 color palette of classic Mac OS: a 6x6x6 color cube without
+/// black, then ramps of red, green, blue and gray without the cube's levels, then black.
+fn palette_8(index: u8) -> [u8; 3] {
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let i = usize::from(index);
+    match i {
+        0..=214 => {
+            let level = |n: usize| 0xff - 0x33 * n as u8;
+            [level(i / 36), level(i / 6 % 6), level(i % 6)]
+        }
+        215..=254 => {
+            let value = RAMP[(i - 215) % 10];
+            match (i - 215) / 10 {
+                0 => [value, 0, 0],
+                1 => [0, value, 0],
+                2 => [0, 0, value],
+                _ => [value, value, value],
+            }
+        }
+        _ => [0, 0, 0],
+    }
+}
+
+/// An RGBA image with 8 bits per channel, in rows from the top.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// A decoded icon element.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Icon {
+    Rgba(Image),
+    /// A PNG file, as newer `icns` elements are stored.
+    Png(Vec<u8>),
+    /// A JPEG 2000 file, as some `icns` elements from Mac OS X 10.5 are stored.
+    Jpeg2000(Vec<u8>),
+}
+
+/// The elements of an icon family, from resources or an `icns` file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IconFamily {
+    pub elements: Vec<(OSType, Vec<u8>)>,
+}
+
+impl IconFamily {
+    /// Collects the icon resources with ID `CUSTOM_ICON_ID`, including the elements of an 'icns'
+    /// resource. Returns `None` if there are none.
+    pub fn from_resource_fork(fork: &ResourceFork) -> Result<Option<IconFamily>> {
+        let mut family = IconFamily::default();
+        for resource in fork.resources.iter().filter(|r| r.id == CUSTOM_ICON_ID) {
+            if resource.resource_type == ICNS {
+                family
+                    .elements
+                    .extend(IconFamily::from_icns(&resource.data)?.elements);
+            } else if element_info(resource.resource_type).is_some() {
+                family
+                    .elements
+                    .push((resource.resource_type, resource.data.clone()));
+            }
+        }
+        Ok(Some(family).filter(|f| !f.elements.is_empty()))
+    }
+
+    /// Parses an `icns` file: 'icns' and the total length, then elements made of a type, a length
+    /// which includes the 8 byte element header, and the data.
+    pub fn from_icns(data: &[u8]) -> Result<IconFamily> {
+        if data.len() < 8 {
+            return Err(Error::Truncated { offset: data.len() });
+        }
+        if data[..4] != ICNS.0 {
+            return Err(Error::InvalidMagic {
+                found: be_u32(data, 0),
+            });
+        }
+        let total = (be_u32(data, 4) as usize).min(data.len());
+        let mut elements = Vec::new();
+        let mut offset = 8;
+        while offset + 8 <= total {
+            let element_type = OSType([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ]);
+            let len = be_u32(data, offset + 4) as usize;
+            if len < 8 || offset + len > total {
+                return Err(Error::Truncated { offset: total });
+            }
+            elements.push((element_type, data[offset + 8..offset + len].to_vec()));
+            offset += len;
+        }
+        Ok(IconFamily { elements })
+    }
+
+    pub fn element(&self, element_type: OSType) -> Option<&[u8]> {
+        self.elements
+            .iter()
+            .find(|(t, _)| *t == element_type)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Decodes an element. Returns `Ok(None)` if the family doesn't have it or it isn't an image
+    /// this module knows, such as a mask or the table of contents.
+    pub fn decode(&self, element_type: OSType) -> Result<Option<Icon>> {
+        let data = match self.element(element_type) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        if data.starts_with(PNG_SIGNATURE) {
+            return Ok(Some(Icon::Png(data.to_vec())));
+        }
+        if data.starts_with(JPEG2000_SIGNATURE) {
+            return Ok(Some(Icon::Jpeg2000(data.to_vec())));
+        }
+        let (width, height, format, mask_type) = match element_info(element_type) {
+            Some(e) => (e.width, e.height, e.format, e.mask),
+            None => return Ok(None),
+        };
+        let pixels = (width * height) as usize;
+        let mask = || -> Result<Vec<u8>> {
+            let mask = mask_type.and_then(|t| self.element(t));
+            Ok(match (format, mask) {
+                (Format::Rgb, Some(mask)) => need(mask, pixels)?.to_vec(),
+                (_, Some(mask)) => {
+                    // The mask follows the icon in the mono element.
+                    let mask = need(mask, pixels / 4)?;
+                    unpack_bits(&mask[pixels / 8..], 1, pixels)
+                        .iter()
+                        .map(|&b| b * 0xff)
+                        .collect()
+                }
+                (_, None) => vec![0xff; pixels],
+            })
+        };
+
+        let mut image = Image {
+            width,
+            height,
+            pixels: Vec::with_capacity(pixels * 4),
+        };
+        match format {
+            Format::Mono => {
+                let data = need(data, pixels / 4)?;
+                let icon = unpack_bits(data, 1, pixels);
+                let mask = unpack_bits(&data[pixels / 8..], 1, pixels);
+                for (&bit, &mask) in icon.iter().zip(&mask) {
+                    let value = if bit == 1 { 0 } else { 0xff };
+                    image
+                        .pixels
+                        .extend_from_slice(&[value, value, value, mask * 0xff]);
+                }
+            }
+            Format::Indexed4 | Format::Indexed8 => {
+                let depth = if let Format::Indexed4 = format { 4 } else { 8 };
+                let indices = unpack_bits(need(data, pixels * depth / 8)?, depth, pixels);
+                for (&index, alpha) in indices.iter().zip(mask()?) {
+                    let rgb = if depth == 4 {
+                        PALETTE_4[usize::from(index)]
+                    } else {
+                        palette_8(index)
+                    };
+                    image
+                        .pixels
+                        .extend_from_slice(&[rgb[0], rgb[1], rgb[2], alpha]);
+                }
+            }
+            Format::Rgb => {
+                let planes = if data.len() == pixels * 4 {
+                    // Some writers store the planes uncompressed, as interleaved ARGB.
+                    let mut planes = vec![0u8; pixels * 3];
+                    for (i, argb) in data.chunks(4).enumerate() {
+                        planes[i] = argb[1];
+                        planes[pixels + i] = argb[2];
+                        planes[2 * pixels + i] = argb[3];
+                    }
+                    planes
+                } else {
+                    // 'it32' data starts with four zero bytes.
+                    let data = if element_type == OSType::new(b"it32") {
+                        data.get(4..).unwrap_or(&[])
+                    } else {
+                        data
+                    };
+                    unpack_rle(data, pixels * 3)?
+                };
+                let alpha = mask()?;
+                for i in 0..pixels {
+                    image.pixels.extend_from_slice(&[
+                        planes[i],
+                        planes[pixels + i],
+                        planes[2 * pixels + i],
+                        alpha[i],
+                    ]);
+                }
+            }
+            Format::Argb => {
+                if !data.starts_with(b"ARGB") {
+                    return Ok(None);
+                }
+                let planes = unpack_rle(&data[4..], pixels * 4)?;
+                for i in 0..pixels {
+                    image.pixels.extend_from_slice(&[
+                        planes[pixels + i],
+                        planes[2 * pixels + i],
+                        planes[3 * pixels + i],
+                        planes[i],
+                    ]);
+                }
+            }
+            Format::Mask | Format::Encoded => return Ok(None),
+        }
+        Ok(Some(Icon::Rgba(image)))
+    }
+
+    /// Decodes the largest image in the family, preferring deeper images of the same size.
+    /// Elements which fail to decode are skipped; their error is returned only if no other
+    /// element decodes.
+    pub fn largest(&self) -> Result<Option<Icon>> {
+        let mut best: Option<((u32, usize), Icon)> = None;
+        let mut error = None;
+        for (rank, element) in ELEMENTS.iter().enumerate() {
+            match self.decode(element.code) {
+                Ok(Some(icon)) => {
+                    let key = (element.width, rank);
+                    match &best {
+                        Some((best_key, _)) if *best_key > key => {}
+                        _ => best = Some((key, icon)),
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        match (best, error) {
+            (Some((_, icon)), _) => Ok(Some(icon)),
+            (None, Some(e)) => Err(e),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+fn element_info(element_type: OSType) -> Option<&'static Element> {
+    ELEMENTS.iter().find(|e| e.code == element_type)
+}
+
+fn need(data: &[u8], len: usize) -> Result<&[u8]> {
+    data.get(..len)
+        .ok_or(Error::Truncated { offset: data.len() })
+}
+
+/// Splits packed pixels of `depth` bits, most significant first, into one byte each.
+fn unpack_bits(data: &[u8], depth: usize, count: usize) -> Vec<u8> {
+    let per_byte = 8 / depth;
+    let mask = u8::MAX >> (8 - depth);
+    (0..count)
+        .map(|i| {
+            let shift = 8 - depth * (i % per_byte + 1);
+            (data[i / per_byte] >> shift) & mask
+        })
+        .collect()
+}
+
+/// Expands the run-length encoding of `icns` planes: a byte below 0x80 is followed by that many
+/// plus one literal bytes, and a byte `n` from 0x80 is followed by a byte repeated `n - 125` times.
+fn unpack_rle(data: &[u8], len: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while out.len() < len {
+        let &control = data.get(i).ok_or(Error::Truncated { offset: data.len() })?;
+        if control < 0x80 {
+            let count = usize::from(control) + 1;
+            out.extend_from_slice(
+                data.get(i + 1..i + 1 + count)
+                    .ok_or(Error::Truncated { offset: data.len() })?,
+            );
+            i += 1 + count;
+        } else {
+            let &value = data
+                .get(i + 1)
+                .ok_or(Error::Truncated { offset: data.len() })?;
+            out.resize(out.len() + usize::from(control) - 125, value);
+            i += 2;
+        }
+    }
+    out.truncate(len);
+    Ok(out)
+}
+
+/// Returns the custom icon if `finder_info` has `kHasCustomIcon` set.
+///
+/// `resource_fork` is the resource fork of the file, or for a folder the resource fork of the
+/// `Icon\r` file inside it.
+pub fn custom_icon(finder_info: &FinderInfo, resource_fork: &[u8]) -> Result<Option<IconFamily>> {
+    // The Finder flags are at the same offset for files and folders.
+    let bytes = finder_info.to_bytes();
+    let flags = FinderFlags::from(u16::from_be_bytes([bytes[8], bytes[9]]));
+    if !flags.has_custom_icon() {
+        return Ok(None);
+    }
+    IconFamily::from_resource_fork(&ResourceFork::from_slice(resource_fork)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource_fork::Resource;
+    use crate::{FinderInfoFile, FinderInfoFolder};
+
+    fn rgba(icon: Option<Icon>) -> Image {
+        match icon {
+            Some(Icon::Rgba(image)) => image,
+            other => panic!("unexpected icon {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_palettes() {
+        assert_eq!(palette_8(0), [0xff, 0xff, 0xff]);
+        assert_eq!(palette_8(5), [0xff, 0xff, 0x00]);
+        assert_eq!(palette_8(214), [0x00, 0x00, 0x33]);
+        assert_eq!(palette_8(215), [0xee, 0x00, 0x00]);
+        assert_eq!(palette_8(245), [0xee, 0xee, 0xee]);
+        assert_eq!(palette_8(254), [0x11, 0x11, 0x11]);
+        assert_eq!(palette_8(255), [0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_classic_icons() {
+        // Black top half, masked to the left half of each row.
+        let mut mono = vec![0u8; 256];
+        mono[..64].iter_mut().for_each(|b| *b = 0xff);
+        mono[128..]
+            .chunks_mut(4)
+            .for_each(|row| row[..2].copy_from_slice(&[0xff, 0xff]));
+        let mut icl8 = vec![0u8; 1024];
+        icl8[0] = 215;
+        icl8[1] = 255;
+
+        let mut fork = ResourceFork::default();
+        fork.insert(Resource::new(OSType::new(b"ICN#"), CUSTOM_ICON_ID, mono));
+        fork.insert(Resource::new(OSType::new(b"icl8"), CUSTOM_ICON_ID, icl8));
+        fork.insert(Resource::new(OSType::new(b"ICN#"), 128, vec![0; 256]));
+        let family = IconFamily::from_resource_fork(&fork).unwrap().unwrap();
+        assert_eq!(family.elements.len(), 2);
+
+        let image = rgba(family.decode(OSType::new(b"ICN#")).unwrap());
+        assert_eq!((image.width, image.height), (32, 32));
+        assert_eq!(&image.pixels[..4], &[0, 0, 0, 0xff]);
+        assert_eq!(&image.pixels[16 * 4..17 * 4], &[0, 0, 0, 0]);
+        assert_eq!(
+            &image.pixels[16 * 32 * 4..16 * 32 * 4 + 4],
+            &[0xff, 0xff, 0xff, 0xff]
+        );
+
+        let image = rgba(family.largest().unwrap());
+        assert_eq!(&image.pixels[..8], &[0xee, 0, 0, 0xff, 0, 0, 0, 0xff]);
+        assert_eq!(image.pixels[31 * 4 + 3], 0);
+
+        // A damaged element is passed over for one that decodes.
+        let mut damaged = family.clone();
+        damaged.elements[1].1.truncate(100);
+        let image = rgba(damaged.largest().unwrap());
+        assert_eq!(&image.pixels[..4], &[0, 0, 0, 0xff]);
+        damaged.elements.remove(0);
+        match damaged.largest() {
+            Err(Error::Truncated { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_icns() {
+        // A 16x16 red square, half transparent, plus a PNG element.
+        // Each plane is a run of 130 and a run of 126.
+        let mut is32 = Vec::new();
+        for &value in &[0xffu8, 0, 0] {
+            is32.extend_from_slice(&[0xff, value, 0xfb, value]);
+        }
+        let png = [PNG_SIGNATURE, b"rest of the file"].concat();
+        let mut icns = b"icns\0\0\0\0".to_vec();
+        for (element_type, data) in &[
+            (b"is32", is32),
+            (b"s8mk", vec![0x80; 256]),
+            (b"ic08", png.clone()),
+        ] {
+            icns.extend_from_slice(*element_type);
+            icns.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+            icns.extend_from_slice(data);
+        }
+        let len = icns.len() as u32;
+        icns[4..8].copy_from_slice(&len.to_be_bytes());
+
+        let family = IconFamily::from_icns(&icns).unwrap();
+        let image = rgba(family.decode(OSType::new(b"is32")).unwrap());
+        assert!(image.pixels.chunks(4).all(|p| p == [0xff, 0, 0, 0x80]));
+        assert_eq!(family.decode(OSType::new(b"s8mk")).unwrap(), None);
+        assert_eq!(family.largest().unwrap(), Some(Icon::Png(png)));
+
+        let mut fork = ResourceFork::default();
+        fork.insert(Resource::new(ICNS, CUSTOM_ICON_ID, icns.clone()));
+        let fork = fork.to_bytes().unwrap();
+        let mut file = FinderInfoFile::default();
+        assert_eq!(custom_icon(&file.into(), &fork).unwrap(), None);
+        file.file_info.finderFlags.set_has_custom_icon(true);
+        assert_eq!(
+            custom_icon(&file.into(), &fork).unwrap(),
+            Some(family.clone())
+        );
+        let mut folder = FinderInfoFolder::default();
+        folder.folder_info.finderFlags.set_has_custom_icon(true);
+        assert_eq!(custom_icon(&folder.into(), &fork).unwrap(), Some(family));
+
+        match IconFamily::from_icns(&icns[..icns.len() - 1]) {
+            Err(Error::Truncated { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        icns[0] = b'I';
+        assert!(IconFamily::from_icns(&icns).is_err());
+    }
+}
@@ -6,18 +6,272 @@
 //! appropriately. APFS isn't big-endian, but it pretends pretty hard internally (and does so
 //! here).
 
+use std::convert::TryFrom;
 use std::fmt;
 use std::io::{self, Read, Write};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub mod afpinfo;
+pub mod appledouble;
+pub mod applesingle;
+pub mod binhex;
+pub mod bplist;
+mod crc;
+mod error;
+pub mod hfsplus;
+pub mod icons;
+pub mod macbinary;
+mod macroman;
+pub mod netatalk;
+#[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+pub mod probe;
+pub mod registry;
+pub mod resource_fork;
+#[cfg(feature = "serde")]
+mod serde_impl;
+pub mod store;
+pub mod tags;
+#[cfg(test)]
+mod test_util;
+#[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+pub mod xattr;
+
+pub use crate::error::{Error, Result};
+
+/// Fills `buf` from `r`, reporting how far it got if the input runs out.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<()> {
+    let mut offset = 0;
+    while offset < buf.len() {
+        match r.read(&mut buf[offset..]) {
+            Ok(0) => return Err(Error::Truncated { offset }),
+            Ok(n) => offset += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
 
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+/// Whether FinderInfo for `path` should be interpreted as file or folder FinderInfo.
+fn kind_of_path(path: &std::path::Path) -> io::Result<FinderInfoKind> {
+    Ok(if std::fs::metadata(path)?.is_dir() {
+        FinderInfoKind::Directory
+    } else {
+        FinderInfoKind::File
+    })
+}
 
-#[derive(Clone, Copy, Default, Eq, PartialEq)]
+/// Splits FinderInfo into its classic and extended halves.
+const fn split_halves(buf: &[u8; 32]) -> ([u8; 16], [u8; 16]) {
+    let mut first = [0u8; 16];
+    let mut second = [0u8; 16];
+    let mut i = 0;
+    while i < 16 {
+        first[i] = buf[i];
+        second[i] = buf[i + 16];
+        i += 1;
+    }
+    (first, second)
+}
+
+const fn join_halves(first: &[u8; 16], second: &[u8; 16]) -> [u8; 32] {
+    let mut buf = [0u8; 32];
+    let mut i = 0;
+    while i < 16 {
+        buf[i] = first[i];
+        buf[i + 16] = second[i];
+        i += 1;
+    }
+    buf
+}
+
+/// Converts a count of seconds since the Unix epoch, as stored by macOS in FinderInfo, to a
+/// `SystemTime`.
+fn unix_time_from_u32(secs: u32) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(u64::from(secs))
+}
+
+fn unix_time_to_u32(time: SystemTime) -> Option<u32> {
+    let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    u32::try_from(secs).ok()
+}
+
+/// Seconds from 1904-01-01 00:00:00, the classic Mac OS epoch, to the Unix epoch.
+const MAC_EPOCH_OFFSET: u64 = 2_082_844_800;
+
+/// Converts a classic Mac OS date, in seconds since 1904, to a `SystemTime`. Classic Mac OS stored
+/// local time; this treats it as UTC.
+fn mac_time_from_u32(secs: u32) -> SystemTime {
+    let secs = u64::from(secs);
+    if secs >= MAC_EPOCH_OFFSET {
+        UNIX_EPOCH + Duration::from_secs(secs - MAC_EPOCH_OFFSET)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(MAC_EPOCH_OFFSET - secs)
+    }
+}
+
+fn mac_time_to_u32(time: SystemTime) -> Option<u32> {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => MAC_EPOCH_OFFSET.checked_add(d.as_secs())?,
+        Err(e) => MAC_EPOCH_OFFSET.checked_sub(e.duration().as_secs())?,
+    };
+    u32::try_from(secs).ok()
+}
+
+fn words_to_u32(hi: i16, lo: i16) -> u32 {
+    (u32::from(hi as u16) << 16) | u32::from(lo as u16)
+}
+
+fn u32_to_words(v: u32) -> (i16, i16) {
+    ((v >> 16) as u16 as i16, v as u16 as i16)
+}
+
+/// Checks that `buf` holds exactly `N` bytes, reporting a short buffer as truncated and a long one
+/// as having trailing bytes, as readers do.
+fn strict_slice<const N: usize>(buf: &[u8]) -> Result<&[u8; N]> {
+    if buf.len() < N {
+        return Err(Error::Truncated { offset: buf.len() });
+    }
+    if buf.len() > N {
+        return Err(Error::TrailingBytes {
+            count: buf.len() - N,
+        });
+    }
+    Ok(TryFrom::try_from(buf).unwrap())
+}
+
+/// Checks that `buf` holds exactly `N` bytes.
+fn exact_slice<const N: usize>(buf: &[u8]) -> Result<&[u8; N]> {
+    TryFrom::try_from(buf).map_err(|_| Error::InvalidLength {
+        expected: N,
+        actual: buf.len(),
+    })
+}
+
+/// Reads a big-endian `u16` at `offset`, which the caller has checked is in bounds.
+pub(crate) fn be_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// Reads a big-endian `u32` at `offset`, which the caller has checked is in bounds.
+pub(crate) fn be_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+/// A four character code, such as a file type or creator.
+///
+/// Codes are conventionally written as four Mac OS Roman characters, e.g. `'TEXT'` or `'©FTP'`.
+#[derive(Clone, Copy, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct OSType(pub [u8; 4]);
 
+impl OSType {
+    /// Builds a code from its bytes, e.g. `OSType::new(b"TEXT")`.
+    pub const fn new(code: &[u8; 4]) -> OSType {
+        OSType(*code)
+    }
+
+    pub const fn from_u32(code: u32) -> OSType {
+        OSType(code.to_be_bytes())
+    }
+
+    pub const fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    /// Returns true for the all-zero code, which is used to mean "no type" or "no creator".
+    pub const fn is_zero(&self) -> bool {
+        self.to_u32() == 0
+    }
+
+    /// Returns true if every byte is a printable Mac OS Roman character.
+    pub fn is_printable(&self) -> bool {
+        self.0.iter().all(|&b| b >= 0x20 && b != 0x7f)
+    }
+
+    /// Parses the characters of a code, which may use `\xNN` and `\\` escapes.
+    fn parse_chars(s: &str) -> Option<OSType> {
+        let mut bytes = vec![];
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                bytes.push(macroman::encode_char(c)?);
+                continue;
+            }
+            match chars.next()? {
+                '\\' => bytes.push(b'\\'),
+                'x' => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    if hex.len() != 2 {
+                        return None;
+                    }
+                    bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+                }
+                _ => return None,
+            }
+        }
+        let code: [u8; 4] = TryFrom::try_from(&bytes[..]).ok()?;
+        Some(OSType(code))
+    }
+}
+
+/// Writes the code as Mac OS Roman characters. Control characters are escaped as `\xNN`, and
+/// backslashes as `\\`, so that the output can be parsed back with `FromStr`.
+impl fmt::Display for OSType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &b in &self.0 {
+            match b {
+                b'\\' => f.write_str("\\\\")?,
+                0x00..=0x1f | 0x7f => write!(f, "\\x{:02X}", b)?,
+                _ => write!(f, "{}", macroman::decode_byte(b))?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Debug for OSType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = String::from_utf8(self.0.to_vec());
-        write!(f, "{:?}", s)
+        write!(f, "'{}'", self)
+    }
+}
+
+/// Parses a code written as four characters (`TEXT`), optionally quoted (`'TEXT'`) or with escapes
+/// (`\xA9FTP`), or as a hexadecimal number (`0x54455854`).
+impl FromStr for OSType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<OSType> {
+        let unquoted = s.strip_prefix('\'').and_then(|s| s.strip_suffix('\''));
+        let hex = s
+            .strip_prefix("0x")
+            .filter(|hex| hex.len() == 8)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+        OSType::parse_chars(s)
+            .or_else(|| unquoted.and_then(OSType::parse_chars))
+            .or_else(|| hex.map(OSType::from_u32))
+            .ok_or_else(|| Error::Parse {
+                input: s.to_string(),
+                expected: "a four character code",
+            })
+    }
+}
+
+impl From<u32> for OSType {
+    fn from(code: u32) -> OSType {
+        OSType::from_u32(code)
+    }
+}
+
+impl From<OSType> for u32 {
+    fn from(code: OSType) -> u32 {
+        code.to_u32()
     }
 }
 
@@ -65,6 +319,8 @@ pub mod constants {
     pub const kExtendedFlagsAreInvalid: u16 = 0x8000;
     /// Set if the file or folder has a badge resource.
     pub const kExtendedFlagHasCustomBadge: u16 = 0x0100;
+    /// Set if the object is marked as busy or incomplete, e.g. while the Finder is copying it.
+    pub const kExtendedFlagObjectIsBusy: u16 = 0x0080;
     /// Set if the file contains routing info resource.
     pub const kExtendedFlagHasRoutingInfo: u16 = 0x0004;
 
@@ -73,9 +329,21 @@ pub mod constants {
     pub const kSymLinkFileType: OSType = OSType([0x73, 0x6c, 0x6e, 0x6b]); /* 'slnk' */
     /// File type for the creator of a symlink.
     pub const kSymLinkCreator: OSType = OSType([0x72, 0x68, 0x61, 0x70]); /* 'rhap' */
+    /// File type for a file hard link on HFS+.
+    pub const kHardLinkFileType: OSType = OSType([0x68, 0x6c, 0x6e, 0x6b]); /* 'hlnk' */
+    /// File type for the creator of a file hard link on HFS+.
+    pub const kHFSPlusCreator: OSType = OSType([0x68, 0x66, 0x73, 0x2b]); /* 'hfs+' */
+    /// File type for a directory hard link on HFS+. Directory hard links are stored as alias files.
+    pub const kHFSAliasType: OSType = OSType([0x66, 0x64, 0x72, 0x70]); /* 'fdrp' */
+    /// File type the Finder gives a file while it is being copied; the creator is
+    /// `kHFSAliasCreator`. Name is not official.
+    pub const kCopyInProgressFileType: OSType = OSType([0x62, 0x72, 0x6f, 0x6b]); /* 'brok' */
+    /// File type for the creator of a directory hard link on HFS+.
+    pub const kHFSAliasCreator: OSType = OSType([0x4d, 0x41, 0x43, 0x53]); /* 'MACS' */
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct Point {
     pub v: i16,
@@ -83,20 +351,32 @@ pub struct Point {
 }
 
 impl Point {
-    pub fn read<R: ReadBytesExt>(r: &mut R) -> io::Result<Point> {
-        let v = r.read_i16::<BigEndian>()?;
-        let h = r.read_i16::<BigEndian>()?;
-        Ok(Point { v, h })
+    pub fn read<R: Read>(r: &mut R) -> Result<Point> {
+        let mut buf = [0u8; 4];
+        read_full(r, &mut buf)?;
+        Ok(Point::from_bytes(&buf))
     }
 
-    pub fn write<W: WriteBytesExt>(&self, w: &mut W) -> io::Result<()> {
-        w.write_i16::<BigEndian>(self.v)?;
-        w.write_i16::<BigEndian>(self.h)?;
-        Ok(())
+    pub const fn from_bytes(buf: &[u8; 4]) -> Point {
+        Point {
+            v: i16::from_be_bytes([buf[0], buf[1]]),
+            h: i16::from_be_bytes([buf[2], buf[3]]),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 4] {
+        let v = self.v.to_be_bytes();
+        let h = self.h.to_be_bytes();
+        [v[0], v[1], h[0], h[1]]
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct Rect {
     pub top: i16,
@@ -106,25 +386,122 @@ pub struct Rect {
 }
 
 impl Rect {
-    pub fn read<R: Read>(r: &mut R) -> io::Result<Rect> {
-        let top = r.read_i16::<BigEndian>()?;
-        let left = r.read_i16::<BigEndian>()?;
-        let bottom = r.read_i16::<BigEndian>()?;
-        let right = r.read_i16::<BigEndian>()?;
-        Ok(Rect {
-            top,
-            left,
-            bottom,
-            right,
-        })
+    pub fn read<R: Read>(r: &mut R) -> Result<Rect> {
+        let mut buf = [0u8; 8];
+        read_full(r, &mut buf)?;
+        Ok(Rect::from_bytes(&buf))
     }
 
-    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        w.write_i16::<BigEndian>(self.top)?;
-        w.write_i16::<BigEndian>(self.left)?;
-        w.write_i16::<BigEndian>(self.bottom)?;
-        w.write_i16::<BigEndian>(self.right)?;
-        Ok(())
+    pub const fn from_bytes(buf: &[u8; 8]) -> Rect {
+        Rect {
+            top: i16::from_be_bytes([buf[0], buf[1]]),
+            left: i16::from_be_bytes([buf[2], buf[3]]),
+            bottom: i16::from_be_bytes([buf[4], buf[5]]),
+            right: i16::from_be_bytes([buf[6], buf[7]]),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 8] {
+        let top = self.top.to_be_bytes();
+        let left = self.left.to_be_bytes();
+        let bottom = self.bottom.to_be_bytes();
+        let right = self.right.to_be_bytes();
+        [
+            top[0], top[1], left[0], left[1], bottom[0], bottom[1], right[0], right[1],
+        ]
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
+}
+
+/// A single Finder flag.
+///
+/// The label color takes up three bits and is not a `Flag`; see `FinderFlags::color`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Flag {
+    IsOnDesk,
+    HideExtension,
+    IsShared,
+    HasNoINITs,
+    HasBeenInited,
+    HasCustomIcon,
+    IsStationery,
+    NameLocked,
+    HasBundle,
+    IsInvisible,
+    IsAlias,
+}
+
+impl Flag {
+    /// Every flag, in order of increasing bit value.
+    pub const ALL: [Flag; 11] = [
+        Flag::IsOnDesk,
+        Flag::HideExtension,
+        Flag::IsShared,
+        Flag::HasNoINITs,
+        Flag::HasBeenInited,
+        Flag::HasCustomIcon,
+        Flag::IsStationery,
+        Flag::NameLocked,
+        Flag::HasBundle,
+        Flag::IsInvisible,
+        Flag::IsAlias,
+    ];
+
+    pub const fn mask(self) -> u16 {
+        match self {
+            Flag::IsOnDesk => constants::kIsOnDesk,
+            Flag::HideExtension => constants::kHideExtension,
+            Flag::IsShared => constants::kIsShared,
+            Flag::HasNoINITs => constants::kHasNoINITs,
+            Flag::HasBeenInited => constants::kHasBeenInited,
+            Flag::HasCustomIcon => constants::kHasCustomIcon,
+            Flag::IsStationery => constants::kIsStationery,
+            Flag::NameLocked => constants::kNameLocked,
+            Flag::HasBundle => constants::kHasBundle,
+            Flag::IsInvisible => constants::kIsInvisible,
+            Flag::IsAlias => constants::kIsAlias,
+        }
+    }
+
+    /// Returns the name of the flag's constant, e.g. `kIsInvisible`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Flag::IsOnDesk => "kIsOnDesk",
+            Flag::HideExtension => "kHideExtension",
+            Flag::IsShared => "kIsShared",
+            Flag::HasNoINITs => "kHasNoINITs",
+            Flag::HasBeenInited => "kHasBeenInited",
+            Flag::HasCustomIcon => "kHasCustomIcon",
+            Flag::IsStationery => "kIsStationery",
+            Flag::NameLocked => "kNameLocked",
+            Flag::HasBundle => "kHasBundle",
+            Flag::IsInvisible => "kIsInvisible",
+            Flag::IsAlias => "kIsAlias",
+        }
+    }
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Flag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Flag> {
+        Flag::ALL
+            .iter()
+            .find(|flag| flag.name() == s)
+            .copied()
+            .ok_or_else(|| Error::Parse {
+                input: s.to_string(),
+                expected: "a Finder flag name",
+            })
     }
 }
 
@@ -132,6 +509,76 @@ impl Rect {
 pub struct FinderFlags(u16);
 
 impl FinderFlags {
+    /// The bits holding the label color.
+    pub const COLOR: FinderFlags = FinderFlags(constants::kColor);
+
+    const KNOWN: u16 = constants::kColor
+        | constants::kIsOnDesk
+        | constants::kHideExtension
+        | constants::kIsShared
+        | constants::kHasNoINITs
+        | constants::kHasBeenInited
+        | constants::kHasCustomIcon
+        | constants::kIsStationery
+        | constants::kNameLocked
+        | constants::kHasBundle
+        | constants::kIsInvisible
+        | constants::kIsAlias;
+
+    pub const fn empty() -> FinderFlags {
+        FinderFlags(0)
+    }
+
+    pub const fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(&self, flag: Flag) -> bool {
+        self.0 & flag.mask() != 0
+    }
+
+    pub fn insert(&mut self, flag: Flag) {
+        self.0 |= flag.mask();
+    }
+
+    pub fn remove(&mut self, flag: Flag) {
+        self.0 &= !flag.mask();
+    }
+
+    pub fn toggle(&mut self, flag: Flag) {
+        self.0 ^= flag.mask();
+    }
+
+    /// Iterates over the flags which are set. The label color and unknown bits are not included.
+    pub fn iter(&self) -> impl Iterator<Item = Flag> {
+        let flags = *self;
+        Flag::ALL
+            .iter()
+            .copied()
+            .filter(move |flag| flags.contains(*flag))
+    }
+
+    /// Returns the bits which are set but are neither a `Flag` nor part of the label color.
+    pub const fn unknown_bits(&self) -> u16 {
+        self.0 & !Self::KNOWN
+    }
+
+    pub fn is_on_desk(&self) -> bool {
+        self.contains(Flag::IsOnDesk)
+    }
+
+    pub fn set_is_on_desk(&mut self, value: bool) {
+        if value {
+            self.insert(Flag::IsOnDesk);
+        } else {
+            self.remove(Flag::IsOnDesk);
+        }
+    }
+
     pub fn color(&self) -> Option<LabelColor> {
         LabelColor::from_u8((self.0 & constants::kColor) as u8)
     }
@@ -268,36 +715,7 @@ impl fmt::Debug for FinderFlags {
         if let Some(color) = self.color() {
             flags.push(format!("{:?}", color));
         }
-        if self.is_shared() {
-            flags.push("kIsShared".to_string());
-        }
-        if self.has_no_inits() {
-            flags.push("kHasNoINITs".to_string());
-        }
-        if self.has_been_inited() {
-            flags.push("kHasBeenInited".to_string());
-        }
-        if self.has_custom_icon() {
-            flags.push("kHasCustomIcon".to_string());
-        }
-        if self.is_stationery() {
-            flags.push("kIsStationery".to_string());
-        }
-        if self.name_locked() {
-            flags.push("kNameLocked".to_string());
-        }
-        if self.has_bundle() {
-            flags.push("kHasBundle".to_string());
-        }
-        if self.is_invisible() {
-            flags.push("kIsInvisible".to_string());
-        }
-        if self.is_alias() {
-            flags.push("kIsAlias".to_string());
-        }
-        if self.has_hidden_extension() {
-            flags.push("kHideExtension".to_string());
-        }
+        flags.extend(self.iter().map(|flag| flag.name().to_string()));
         f.debug_struct("FinderFlags")
             .field("raw", &self.0)
             .field("flags", &flags)
@@ -305,6 +723,51 @@ impl fmt::Debug for FinderFlags {
     }
 }
 
+/// Formats the flags as their names separated by `|`, e.g. `Red | kIsInvisible`. Unknown bits are
+/// written in hex, and no flags at all as `0`.
+impl fmt::Display for FinderFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(color) = self.color() {
+            parts.push(LabelColor::to_str(color).to_string());
+        }
+        parts.extend(self.iter().map(|flag| flag.name().to_string()));
+        if self.unknown_bits() != 0 {
+            parts.push(format!("{:#06x}", self.unknown_bits()));
+        }
+        if parts.is_empty() {
+            return f.write_str("0");
+        }
+        f.write_str(&parts.join(" | "))
+    }
+}
+
+/// Parses the format produced by `Display`. Numbers may also be given in decimal.
+impl FromStr for FinderFlags {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<FinderFlags> {
+        let mut flags = FinderFlags::empty();
+        for part in s.split('|').map(str::trim) {
+            if let Some(color) = LabelColor::from_str(part) {
+                flags.set_color(Some(color));
+            } else if let Ok(flag) = part.parse::<Flag>() {
+                flags.insert(flag);
+            } else {
+                let bits = match part.strip_prefix("0x") {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => part.parse(),
+                };
+                flags.0 |= bits.map_err(|_| Error::Parse {
+                    input: part.to_string(),
+                    expected: "a Finder flag name, label color or number",
+                })?;
+            }
+        }
+        Ok(flags)
+    }
+}
+
 impl From<u16> for FinderFlags {
     fn from(s: u16) -> FinderFlags {
         FinderFlags(s)
@@ -316,11 +779,68 @@ impl From<FinderFlags> for u16 {
     }
 }
 
-// TODO(robert): In MacOS 10.10 and above, the `LabelColor` is no longer stored in the
-// `com.apple.FinderInfo` attribute but is instead stored in a `bplist` format. The last tag-string
-// in the `bplist` which corresponds to a color is the one which we should set in the
-// `com.apple.FinderInfo` attribute. We should synchronize these on write/read to be
-// cross-compatible with MacOS 10.9.
+impl From<Flag> for FinderFlags {
+    fn from(flag: Flag) -> FinderFlags {
+        FinderFlags(flag.mask())
+    }
+}
+
+impl<T: Into<FinderFlags>> BitOr<T> for FinderFlags {
+    type Output = FinderFlags;
+
+    fn bitor(self, rhs: T) -> FinderFlags {
+        FinderFlags(self.0 | rhs.into().0)
+    }
+}
+
+impl<T: Into<FinderFlags>> BitOrAssign<T> for FinderFlags {
+    fn bitor_assign(&mut self, rhs: T) {
+        self.0 |= rhs.into().0;
+    }
+}
+
+impl<T: Into<FinderFlags>> BitAnd<T> for FinderFlags {
+    type Output = FinderFlags;
+
+    fn bitand(self, rhs: T) -> FinderFlags {
+        FinderFlags(self.0 & rhs.into().0)
+    }
+}
+
+impl<T: Into<FinderFlags>> BitAndAssign<T> for FinderFlags {
+    fn bitand_assign(&mut self, rhs: T) {
+        self.0 &= rhs.into().0;
+    }
+}
+
+impl Not for FinderFlags {
+    type Output = FinderFlags;
+
+    fn not(self) -> FinderFlags {
+        FinderFlags(!self.0)
+    }
+}
+
+impl<T: Into<FinderFlags>> BitOr<T> for Flag {
+    type Output = FinderFlags;
+
+    fn bitor(self, rhs: T) -> FinderFlags {
+        FinderFlags::from(self) | rhs
+    }
+}
+
+impl Not for Flag {
+    type Output = FinderFlags;
+
+    fn not(self) -> FinderFlags {
+        !FinderFlags::from(self)
+    }
+}
+
+/// A Finder label color.
+///
+/// Newer versions of macOS store colors as tags in a separate extended attribute and only mirror
+/// the last one here; see `tags::sync_label_color` to keep the two consistent.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum LabelColor {
     Gray,
@@ -371,6 +891,7 @@ impl LabelColor {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<LabelColor> {
         match s {
             "Gray" => Some(LabelColor::Gray),
@@ -397,8 +918,11 @@ impl fmt::Debug for ExtendedFinderFlags {
         if self.has_custom_badge() {
             flags.push("kExtendedFlagHasCustomBadge");
         }
+        if self.is_busy() {
+            flags.push("kExtendedFlagObjectIsBusy");
+        }
         if self.has_routing_info() {
-            flags.push("kExtendedFlagHasCustomBadge");
+            flags.push("kExtendedFlagHasRoutingInfo");
         }
         f.debug_struct("ExtendedFinderFlags")
             .field("raw", &self.0)
@@ -408,17 +932,81 @@ impl fmt::Debug for ExtendedFinderFlags {
 }
 
 impl ExtendedFinderFlags {
+    const KNOWN: u16 = constants::kExtendedFlagsAreInvalid
+        | constants::kExtendedFlagHasCustomBadge
+        | constants::kExtendedFlagObjectIsBusy
+        | constants::kExtendedFlagHasRoutingInfo;
+
+    fn set(&mut self, mask: u16, value: bool) {
+        if value {
+            self.0 |= mask;
+        } else {
+            self.0 &= !mask;
+        }
+    }
+
     pub fn are_invalid(&self) -> bool {
         self.0 & constants::kExtendedFlagsAreInvalid != 0
     }
 
+    pub fn set_are_invalid(&mut self, value: bool) {
+        self.set(constants::kExtendedFlagsAreInvalid, value);
+    }
+
     pub fn has_custom_badge(&self) -> bool {
         self.0 & constants::kExtendedFlagHasCustomBadge != 0
     }
 
+    pub fn set_has_custom_badge(&mut self, value: bool) {
+        self.set(constants::kExtendedFlagHasCustomBadge, value);
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.0 & constants::kExtendedFlagObjectIsBusy != 0
+    }
+
+    pub fn set_is_busy(&mut self, value: bool) {
+        self.set(constants::kExtendedFlagObjectIsBusy, value);
+    }
+
     pub fn has_routing_info(&self) -> bool {
         self.0 & constants::kExtendedFlagHasRoutingInfo != 0
     }
+
+    pub fn set_has_routing_info(&mut self, value: bool) {
+        self.set(constants::kExtendedFlagHasRoutingInfo, value);
+    }
+
+    pub const fn bits(&self) -> u16 {
+        self.0
+    }
+
+    /// Whether any of the bits in `mask` are set.
+    pub const fn contains_bits(&self, mask: u16) -> bool {
+        self.0 & mask != 0
+    }
+
+    /// Sets or clears the bits in `mask`.
+    ///
+    /// Finder.h only names the four flags with accessors above. The bits recent versions of macOS
+    /// set alongside the date added and document ID have no published names or meanings, so rather
+    /// than guess at them this crate leaves them to `unknown_bits` and lets callers that know what
+    /// they are doing set them here.
+    pub fn set_bits(&mut self, mask: u16, value: bool) {
+        self.set(mask, value);
+    }
+
+    /// Returns the bits which are set but don't correspond to any flag in `constants`.
+    ///
+    /// Recent versions of macOS set bits in this field which Apple hasn't documented; they are
+    /// preserved as-is, and can be cleared with `clear_unknown_bits`.
+    pub fn unknown_bits(&self) -> u16 {
+        self.0 & !Self::KNOWN
+    }
+
+    pub fn clear_unknown_bits(&mut self) {
+        self.0 &= Self::KNOWN;
+    }
 }
 
 impl From<u16> for ExtendedFinderFlags {
@@ -435,7 +1023,8 @@ impl From<ExtendedFinderFlags> for u16 {
 /// Defines a file information structure.
 ///
 /// The `FileInfo` structure is preferred over the FInfo structure.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct FileInfo {
     /// File type.
@@ -451,40 +1040,62 @@ pub struct FileInfo {
 }
 
 impl FileInfo {
-    pub fn read<R: Read>(r: &mut R) -> io::Result<FileInfo> {
-        let mut fileType = [0u8; 4];
-        r.read(&mut fileType)?;
-        let mut fileCreator = [0u8; 4];
-        r.read(&mut fileCreator)?;
-        let finderFlags = r.read_u16::<BigEndian>()?.into();
-        let location = Point::read(r)?;
-        let reservedField = r.read_u16::<BigEndian>()?;
-        Ok(FileInfo {
-            fileType: OSType(fileType),
-            fileCreator: OSType(fileCreator),
-            finderFlags,
-            location,
-            reservedField,
-        })
+    pub fn read<R: Read>(r: &mut R) -> Result<FileInfo> {
+        let mut buf = [0u8; 16];
+        read_full(r, &mut buf)?;
+        Ok(FileInfo::from_bytes(&buf))
     }
 
-    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        w.write(&self.fileType.0)?;
-        w.write(&self.fileCreator.0)?;
-        w.write_u16::<BigEndian>(self.finderFlags.into())?;
-        self.location.write(w)?;
-        w.write_u16::<BigEndian>(self.reservedField)?;
-        Ok(())
+    pub const fn from_bytes(buf: &[u8; 16]) -> FileInfo {
+        FileInfo {
+            fileType: OSType([buf[0], buf[1], buf[2], buf[3]]),
+            fileCreator: OSType([buf[4], buf[5], buf[6], buf[7]]),
+            finderFlags: FinderFlags(u16::from_be_bytes([buf[8], buf[9]])),
+            location: Point::from_bytes(&[buf[10], buf[11], buf[12], buf[13]]),
+            reservedField: u16::from_be_bytes([buf[14], buf[15]]),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 16] {
+        let fileType = self.fileType.0;
+        let fileCreator = self.fileCreator.0;
+        let finderFlags = self.finderFlags.0.to_be_bytes();
+        let location = self.location.to_bytes();
+        let reservedField = self.reservedField.to_be_bytes();
+        [
+            fileType[0],
+            fileType[1],
+            fileType[2],
+            fileType[3],
+            fileCreator[0],
+            fileCreator[1],
+            fileCreator[2],
+            fileCreator[3],
+            finderFlags[0],
+            finderFlags[1],
+            location[0],
+            location[1],
+            location[2],
+            location[3],
+            reservedField[0],
+            reservedField[1],
+        ]
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
     }
 }
 
 /// Defines an extended file information structure.
 ///
 /// The `ExtendedFileInfo` structure is preferred over the FXInfo structure.
-#[derive(Clone, Default)]
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct ExtendedFileInfo {
-    /// Reserved (set to 0).
+    /// Reserved in classic Mac OS. Modern versions of macOS store the document ID and the date the
+    /// file was added to its folder here; see `document_id` and `date_added`.
     pub reserved1: [i16; 4],
     /// Extended flags. See `ExtendedFinderFlags`.
     pub extendedFinderFlags: ExtendedFinderFlags,
@@ -514,32 +1125,120 @@ impl fmt::Debug for ExtendedFileInfo {
 }
 
 impl ExtendedFileInfo {
-    pub fn read<R: Read>(r: &mut R) -> io::Result<ExtendedFileInfo> {
-        let mut reserved1 = [0i16; 4];
-        r.read_i16_into::<BigEndian>(&mut reserved1)?;
-        let extendedFinderFlags = r.read_u16::<BigEndian>()?.into();
-        let reserved2 = r.read_i16::<BigEndian>()?;
-        let putAwayFolderID = r.read_i32::<BigEndian>()?;
-        Ok(ExtendedFileInfo {
-            reserved1,
-            extendedFinderFlags,
-            reserved2,
-            putAwayFolderID,
-        })
+    pub fn read<R: Read>(r: &mut R) -> Result<ExtendedFileInfo> {
+        let mut buf = [0u8; 16];
+        read_full(r, &mut buf)?;
+        Ok(ExtendedFileInfo::from_bytes(&buf))
+    }
+
+    pub const fn from_bytes(buf: &[u8; 16]) -> ExtendedFileInfo {
+        ExtendedFileInfo {
+            reserved1: [
+                i16::from_be_bytes([buf[0], buf[1]]),
+                i16::from_be_bytes([buf[2], buf[3]]),
+                i16::from_be_bytes([buf[4], buf[5]]),
+                i16::from_be_bytes([buf[6], buf[7]]),
+            ],
+            extendedFinderFlags: ExtendedFinderFlags(u16::from_be_bytes([buf[8], buf[9]])),
+            reserved2: i16::from_be_bytes([buf[10], buf[11]]),
+            putAwayFolderID: i32::from_be_bytes([buf[12], buf[13], buf[14], buf[15]]),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 16] {
+        let r0 = self.reserved1[0].to_be_bytes();
+        let r1 = self.reserved1[1].to_be_bytes();
+        let r2 = self.reserved1[2].to_be_bytes();
+        let r3 = self.reserved1[3].to_be_bytes();
+        let extendedFinderFlags = self.extendedFinderFlags.0.to_be_bytes();
+        let reserved2 = self.reserved2.to_be_bytes();
+        let putAwayFolderID = self.putAwayFolderID.to_be_bytes();
+        [
+            r0[0],
+            r0[1],
+            r1[0],
+            r1[1],
+            r2[0],
+            r2[1],
+            r3[0],
+            r3[1],
+            extendedFinderFlags[0],
+            extendedFinderFlags[1],
+            reserved2[0],
+            reserved2[1],
+            putAwayFolderID[0],
+            putAwayFolderID[1],
+            putAwayFolderID[2],
+            putAwayFolderID[3],
+        ]
+    }
+
+    /// Returns the document ID macOS uses to track the file across saves, if it has one.
+    pub fn document_id(&self) -> Option<u32> {
+        if self.extendedFinderFlags.are_invalid() {
+            return None;
+        }
+        match words_to_u32(self.reserved1[0], self.reserved1[1]) {
+            0 => None,
+            id => Some(id),
+        }
     }
 
-    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        for r in &self.reserved1 {
-            w.write_i16::<BigEndian>(*r)?;
+    /// Sets the document ID. Setting an ID also marks the extended flags as valid.
+    pub fn set_document_id(&mut self, id: Option<u32>) {
+        let (hi, lo) = u32_to_words(id.unwrap_or(0));
+        self.reserved1[0] = hi;
+        self.reserved1[1] = lo;
+        if id.is_some() {
+            self.extendedFinderFlags.set_are_invalid(false);
+        }
+    }
+
+    /// Returns the date the file was added to its folder, if it has been recorded.
+    pub fn date_added(&self) -> Option<SystemTime> {
+        if self.extendedFinderFlags.are_invalid() {
+            return None;
+        }
+        match words_to_u32(self.reserved1[2], self.reserved1[3]) {
+            0 => None,
+            secs => Some(unix_time_from_u32(secs)),
+        }
+    }
+
+    /// Sets the date the file was added to its folder. Setting a date also marks the extended flags
+    /// as valid.
+    ///
+    /// The date is stored as a 32-bit count of seconds since the Unix epoch; dates which can't be
+    /// represented that way are rejected.
+    pub fn set_date_added(&mut self, date: Option<SystemTime>) -> Result<()> {
+        let secs = match date {
+            Some(date) => unix_time_to_u32(date).ok_or(Error::DateOutOfRange)?,
+            None => 0,
+        };
+        let (hi, lo) = u32_to_words(secs);
+        self.reserved1[2] = hi;
+        self.reserved1[3] = lo;
+        if date.is_some() {
+            self.extendedFinderFlags.set_are_invalid(false);
+        }
+        Ok(())
+    }
+
+    /// Checks that the reserved fields are zero.
+    pub fn validate(&self) -> Result<()> {
+        if self.reserved2 != 0 {
+            return Err(Error::ReservedField { field: "reserved2" });
         }
-        w.write_u16::<BigEndian>(self.extendedFinderFlags.into())?;
-        w.write_i16::<BigEndian>(self.reserved2)?;
-        w.write_i32::<BigEndian>(self.putAwayFolderID)?;
         Ok(())
     }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct FinderInfoFile {
     pub file_info: FileInfo,
@@ -547,26 +1246,75 @@ pub struct FinderInfoFile {
 }
 
 impl FinderInfoFile {
-    pub fn read<R: Read>(r: &mut R) -> io::Result<FinderInfoFile> {
-        let file_info = FileInfo::read(r)?;
-        let extended_file_info = ExtendedFileInfo::read(r)?;
-        Ok(FinderInfoFile {
-            file_info,
-            extended_file_info,
-        })
+    /// Reads exactly 32 bytes of FinderInfo.
+    pub fn read<R: Read>(r: &mut R) -> Result<FinderInfoFile> {
+        let mut buf = [0u8; 32];
+        read_full(r, &mut buf)?;
+        Ok(FinderInfoFile::from_bytes(&buf))
     }
 
-    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        self.file_info.write(w)?;
-        self.extended_file_info.write(w)?;
-        Ok(())
+    /// Parses FinderInfo from a buffer which must be exactly 32 bytes long.
+    pub fn from_slice(buf: &[u8]) -> Result<FinderInfoFile> {
+        strict_slice::<32>(buf).map(FinderInfoFile::from_bytes)
+    }
+
+    pub const fn from_bytes(buf: &[u8; 32]) -> FinderInfoFile {
+        let (file_info, extended_file_info) = split_halves(buf);
+        FinderInfoFile {
+            file_info: FileInfo::from_bytes(&file_info),
+            extended_file_info: ExtendedFileInfo::from_bytes(&extended_file_info),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 32] {
+        join_halves(
+            &self.file_info.to_bytes(),
+            &self.extended_file_info.to_bytes(),
+        )
+    }
+
+    /// Checks that the reserved fields are zero.
+    pub fn validate(&self) -> Result<()> {
+        self.extended_file_info.validate()
+    }
+
+    /// Classifies the file from its type, creator and Finder flags.
+    ///
+    /// The special type/creator pairs are checked before the flags, since directory hard links
+    /// also have `kIsAlias` set.
+    pub fn kind(&self) -> ObjectKind {
+        use constants::*;
+
+        let info = &self.file_info;
+        match (info.fileType, info.fileCreator) {
+            (kSymLinkFileType, kSymLinkCreator) => return ObjectKind::SymbolicLink,
+            (kHardLinkFileType, kHFSPlusCreator) => return ObjectKind::HardLink,
+            (kHFSAliasType, kHFSAliasCreator) => return ObjectKind::DirectoryHardLink,
+            (kCopyInProgressFileType, kHFSAliasCreator) => return ObjectKind::CopyInProgress,
+            _ => {}
+        }
+        let flags = info.finderFlags;
+        if flags.is_alias() {
+            ObjectKind::Alias
+        } else if flags.is_stationery() {
+            ObjectKind::Stationery
+        } else if flags.has_bundle() {
+            ObjectKind::Bundle
+        } else {
+            ObjectKind::File
+        }
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
     }
 }
 
 /// Defines a directory information structure.
 ///
 /// The `FolderInfo` structure is preferred over the DInfo structure.
-#[derive(Clone, Default)]
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct FolderInfo {
     /// The rectangle for the window that the Finder displays when the user opens the folder.
@@ -599,38 +1347,77 @@ impl fmt::Debug for FolderInfo {
 }
 
 impl FolderInfo {
-    pub fn read<R: Read>(r: &mut R) -> io::Result<FolderInfo> {
-        let windowBounds = Rect::read(r)?;
-        let finderFlags = r.read_u16::<BigEndian>()?.into();
-        let location = Point::read(r)?;
-        let reservedField = r.read_u16::<BigEndian>()?;
-        Ok(FolderInfo {
-            windowBounds,
-            finderFlags,
-            location,
-            reservedField,
-        })
+    pub fn read<R: Read>(r: &mut R) -> Result<FolderInfo> {
+        let mut buf = [0u8; 16];
+        read_full(r, &mut buf)?;
+        Ok(FolderInfo::from_bytes(&buf))
+    }
+
+    pub const fn from_bytes(buf: &[u8; 16]) -> FolderInfo {
+        FolderInfo {
+            windowBounds: Rect::from_bytes(&[
+                buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], buf[6], buf[7],
+            ]),
+            finderFlags: FinderFlags(u16::from_be_bytes([buf[8], buf[9]])),
+            location: Point::from_bytes(&[buf[10], buf[11], buf[12], buf[13]]),
+            reservedField: u16::from_be_bytes([buf[14], buf[15]]),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 16] {
+        let windowBounds = self.windowBounds.to_bytes();
+        let finderFlags = self.finderFlags.0.to_be_bytes();
+        let location = self.location.to_bytes();
+        let reservedField = self.reservedField.to_be_bytes();
+        [
+            windowBounds[0],
+            windowBounds[1],
+            windowBounds[2],
+            windowBounds[3],
+            windowBounds[4],
+            windowBounds[5],
+            windowBounds[6],
+            windowBounds[7],
+            finderFlags[0],
+            finderFlags[1],
+            location[0],
+            location[1],
+            location[2],
+            location[3],
+            reservedField[0],
+            reservedField[1],
+        ]
     }
 
-    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        self.windowBounds.write(w)?;
-        w.write_u16::<BigEndian>(self.finderFlags.into())?;
-        self.location.write(w)?;
-        w.write_u16::<BigEndian>(self.reservedField)?;
+    /// Checks that the reserved fields are zero.
+    pub fn validate(&self) -> Result<()> {
+        if self.reservedField != 0 {
+            return Err(Error::ReservedField {
+                field: "reservedField",
+            });
+        }
         Ok(())
     }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
 }
 
 /// Defines an extended directory information structure.
 ///
 /// The `ExtendedFolderInfo` structure is preferred over the DXInfo structure.
-#[derive(Clone, Default)]
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct ExtendedFolderInfo {
     /// Scroll position within the Finder window.
     /// The Finder does not necessarily save this position immediately upon user action.
+    ///
+    /// Modern versions of macOS store the document ID here instead; see `document_id`.
     pub scrollPosition: Point,
-    /// Reserved (set to 0).
+    /// Reserved in classic Mac OS. Modern versions of macOS store the date the folder was added to
+    /// its parent here; see `date_added`.
     pub reserved1: i32,
     /// Extended Finder flags. See `ExtendedFinderFlags`.
     pub extendedFinderFlags: ExtendedFinderFlags,
@@ -662,32 +1449,111 @@ impl fmt::Debug for ExtendedFolderInfo {
 }
 
 impl ExtendedFolderInfo {
-    pub fn read<R: Read>(r: &mut R) -> io::Result<ExtendedFolderInfo> {
-        let scrollPosition = Point::read(r)?;
-        let reserved1 = r.read_i32::<BigEndian>()?;
-        let extendedFinderFlags = r.read_u16::<BigEndian>()?.into();
-        let reserved2 = r.read_i16::<BigEndian>()?;
-        let putAwayFolderID = r.read_i32::<BigEndian>()?;
-        Ok(ExtendedFolderInfo {
-            scrollPosition,
-            reserved1,
-            extendedFinderFlags,
-            reserved2,
-            putAwayFolderID,
-        })
+    pub fn read<R: Read>(r: &mut R) -> Result<ExtendedFolderInfo> {
+        let mut buf = [0u8; 16];
+        read_full(r, &mut buf)?;
+        Ok(ExtendedFolderInfo::from_bytes(&buf))
+    }
+
+    pub const fn from_bytes(buf: &[u8; 16]) -> ExtendedFolderInfo {
+        ExtendedFolderInfo {
+            scrollPosition: Point::from_bytes(&[buf[0], buf[1], buf[2], buf[3]]),
+            reserved1: i32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            extendedFinderFlags: ExtendedFinderFlags(u16::from_be_bytes([buf[8], buf[9]])),
+            reserved2: i16::from_be_bytes([buf[10], buf[11]]),
+            putAwayFolderID: i32::from_be_bytes([buf[12], buf[13], buf[14], buf[15]]),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 16] {
+        let scrollPosition = self.scrollPosition.to_bytes();
+        let reserved1 = self.reserved1.to_be_bytes();
+        let extendedFinderFlags = self.extendedFinderFlags.0.to_be_bytes();
+        let reserved2 = self.reserved2.to_be_bytes();
+        let putAwayFolderID = self.putAwayFolderID.to_be_bytes();
+        [
+            scrollPosition[0],
+            scrollPosition[1],
+            scrollPosition[2],
+            scrollPosition[3],
+            reserved1[0],
+            reserved1[1],
+            reserved1[2],
+            reserved1[3],
+            extendedFinderFlags[0],
+            extendedFinderFlags[1],
+            reserved2[0],
+            reserved2[1],
+            putAwayFolderID[0],
+            putAwayFolderID[1],
+            putAwayFolderID[2],
+            putAwayFolderID[3],
+        ]
+    }
+
+    /// Returns the document ID macOS uses to track the folder, if it has one.
+    pub fn document_id(&self) -> Option<u32> {
+        if self.extendedFinderFlags.are_invalid() {
+            return None;
+        }
+        match u32::from_be_bytes(self.scrollPosition.to_bytes()) {
+            0 => None,
+            id => Some(id),
+        }
+    }
+
+    /// Sets the document ID, overwriting the scroll position. Setting an ID also marks the extended
+    /// flags as valid.
+    pub fn set_document_id(&mut self, id: Option<u32>) {
+        self.scrollPosition = Point::from_bytes(&id.unwrap_or(0).to_be_bytes());
+        if id.is_some() {
+            self.extendedFinderFlags.set_are_invalid(false);
+        }
+    }
+
+    /// Returns the date the folder was added to its parent, if it has been recorded.
+    pub fn date_added(&self) -> Option<SystemTime> {
+        if self.extendedFinderFlags.are_invalid() {
+            return None;
+        }
+        match self.reserved1 as u32 {
+            0 => None,
+            secs => Some(unix_time_from_u32(secs)),
+        }
+    }
+
+    /// Sets the date the folder was added to its parent. Setting a date also marks the extended
+    /// flags as valid.
+    ///
+    /// The date is stored as a 32-bit count of seconds since the Unix epoch; dates which can't be
+    /// represented that way are rejected.
+    pub fn set_date_added(&mut self, date: Option<SystemTime>) -> Result<()> {
+        let secs = match date {
+            Some(date) => unix_time_to_u32(date).ok_or(Error::DateOutOfRange)?,
+            None => 0,
+        };
+        self.reserved1 = secs as i32;
+        if date.is_some() {
+            self.extendedFinderFlags.set_are_invalid(false);
+        }
+        Ok(())
     }
 
-    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        self.scrollPosition.write(w)?;
-        w.write_i32::<BigEndian>(self.reserved1)?;
-        w.write_u16::<BigEndian>(self.extendedFinderFlags.into())?;
-        w.write_i16::<BigEndian>(self.reserved2)?;
-        w.write_i32::<BigEndian>(self.putAwayFolderID)?;
+    /// Checks that the reserved fields are zero.
+    pub fn validate(&self) -> Result<()> {
+        if self.reserved2 != 0 {
+            return Err(Error::ReservedField { field: "reserved2" });
+        }
         Ok(())
     }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[repr(C)]
 pub struct FinderInfoFolder {
     pub folder_info: FolderInfo,
@@ -695,19 +1561,314 @@ pub struct FinderInfoFolder {
 }
 
 impl FinderInfoFolder {
-    pub fn read<R: Read>(r: &mut R) -> io::Result<FinderInfoFolder> {
-        let folder_info = FolderInfo::read(r)?;
-        let extended_folder_info = ExtendedFolderInfo::read(r)?;
-        Ok(FinderInfoFolder {
-            folder_info,
-            extended_folder_info,
+    /// Reads exactly 32 bytes of FinderInfo.
+    pub fn read<R: Read>(r: &mut R) -> Result<FinderInfoFolder> {
+        let mut buf = [0u8; 32];
+        read_full(r, &mut buf)?;
+        Ok(FinderInfoFolder::from_bytes(&buf))
+    }
+
+    /// Parses FinderInfo from a buffer which must be exactly 32 bytes long.
+    pub fn from_slice(buf: &[u8]) -> Result<FinderInfoFolder> {
+        strict_slice::<32>(buf).map(FinderInfoFolder::from_bytes)
+    }
+
+    pub const fn from_bytes(buf: &[u8; 32]) -> FinderInfoFolder {
+        let (folder_info, extended_folder_info) = split_halves(buf);
+        FinderInfoFolder {
+            folder_info: FolderInfo::from_bytes(&folder_info),
+            extended_folder_info: ExtendedFolderInfo::from_bytes(&extended_folder_info),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 32] {
+        join_halves(
+            &self.folder_info.to_bytes(),
+            &self.extended_folder_info.to_bytes(),
+        )
+    }
+
+    /// Checks that the reserved fields are zero.
+    pub fn validate(&self) -> Result<()> {
+        self.folder_info.validate()?;
+        self.extended_folder_info.validate()
+    }
+
+    /// Classifies the folder from its Finder flags.
+    pub fn kind(&self) -> ObjectKind {
+        if self.folder_info.finderFlags.has_bundle() {
+            ObjectKind::Package
+        } else {
+            ObjectKind::Folder
+        }
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
+}
+
+impl TryFrom<&[u8]> for FileInfo {
+    type Error = Error;
+
+    fn try_from(buf: &[u8]) -> Result<FileInfo> {
+        exact_slice::<16>(buf).map(FileInfo::from_bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for ExtendedFileInfo {
+    type Error = Error;
+
+    fn try_from(buf: &[u8]) -> Result<ExtendedFileInfo> {
+        exact_slice::<16>(buf).map(ExtendedFileInfo::from_bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for FolderInfo {
+    type Error = Error;
+
+    fn try_from(buf: &[u8]) -> Result<FolderInfo> {
+        exact_slice::<16>(buf).map(FolderInfo::from_bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for ExtendedFolderInfo {
+    type Error = Error;
+
+    fn try_from(buf: &[u8]) -> Result<ExtendedFolderInfo> {
+        exact_slice::<16>(buf).map(ExtendedFolderInfo::from_bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for FinderInfoFile {
+    type Error = Error;
+
+    fn try_from(buf: &[u8]) -> Result<FinderInfoFile> {
+        exact_slice::<32>(buf).map(FinderInfoFile::from_bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for FinderInfoFolder {
+    type Error = Error;
+
+    fn try_from(buf: &[u8]) -> Result<FinderInfoFolder> {
+        exact_slice::<32>(buf).map(FinderInfoFolder::from_bytes)
+    }
+}
+
+impl From<[u8; 32]> for FinderInfoFile {
+    fn from(buf: [u8; 32]) -> FinderInfoFile {
+        FinderInfoFile::from_bytes(&buf)
+    }
+}
+
+impl From<FinderInfoFile> for [u8; 32] {
+    fn from(fi: FinderInfoFile) -> [u8; 32] {
+        fi.to_bytes()
+    }
+}
+
+impl From<[u8; 32]> for FinderInfoFolder {
+    fn from(buf: [u8; 32]) -> FinderInfoFolder {
+        FinderInfoFolder::from_bytes(&buf)
+    }
+}
+
+impl From<FinderInfoFolder> for [u8; 32] {
+    fn from(fi: FinderInfoFolder) -> [u8; 32] {
+        fi.to_bytes()
+    }
+}
+
+/// The kind of filesystem object a FinderInfo blob belongs to.
+///
+/// The 32 bytes of `com.apple.FinderInfo` are laid out differently for files and directories, and
+/// nothing in the blob itself says which layout is in use; callers have to know what they read it
+/// from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum FinderInfoKind {
+    File,
+    Directory,
+}
+
+/// What a filesystem object really is, as far as its FinderInfo can tell.
+///
+/// Returned by `FinderInfoFile::kind` and `FinderInfoFolder::kind`. Several of these are plain
+/// files on disk which the Finder and HFS+ treat specially because of their type, creator or flags.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[non_exhaustive]
+pub enum ObjectKind {
+    /// An ordinary file.
+    File,
+    /// An ordinary folder.
+    Folder,
+    /// An HFS+ symbolic link ('slnk'/'rhap'). The data fork holds the target path.
+    SymbolicLink,
+    /// An HFS+ file hard link ('hlnk'/'hfs+'), pointing at an inode in the private metadata folder.
+    HardLink,
+    /// An HFS+ directory hard link ('fdrp'/'MACS').
+    DirectoryHardLink,
+    /// A Finder alias file (`kIsAlias`).
+    Alias,
+    /// A stationery pad (`kIsStationery`).
+    Stationery,
+    /// A file with a bundle resource (`kHasBundle`), usually a classic application.
+    Bundle,
+    /// A folder the Finder shows as a single file (`kHasBundle`).
+    Package,
+    /// A file the Finder is still copying ('brok'/'MACS').
+    CopyInProgress,
+}
+
+/// FinderInfo for either a file or a directory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum FinderInfo {
+    File(FinderInfoFile),
+    Directory(FinderInfoFolder),
+    /// Raw FinderInfo for an object whose kind isn't known yet. See `FinderInfo::resolve`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::hex"))]
+    Unknown([u8; 32]),
+}
+
+impl FinderInfo {
+    /// Reads 32 bytes of FinderInfo without interpreting them.
+    pub fn read<R: Read>(r: &mut R) -> Result<FinderInfo> {
+        let mut buf = [0u8; 32];
+        read_full(r, &mut buf)?;
+        Ok(FinderInfo::Unknown(buf))
+    }
+
+    /// Reads FinderInfo laid out for the given kind of object.
+    pub fn read_as<R: Read>(kind: FinderInfoKind, r: &mut R) -> Result<FinderInfo> {
+        Ok(match kind {
+            FinderInfoKind::File => FinderInfo::File(FinderInfoFile::read(r)?),
+            FinderInfoKind::Directory => FinderInfo::Directory(FinderInfoFolder::read(r)?),
         })
     }
 
-    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        self.folder_info.write(w)?;
-        self.extended_folder_info.write(w)?;
-        Ok(())
+    /// Parses FinderInfo for the given kind of object from a buffer which must be exactly 32 bytes
+    /// long.
+    pub fn from_slice_as(kind: FinderInfoKind, buf: &[u8]) -> Result<FinderInfo> {
+        Ok(match kind {
+            FinderInfoKind::File => FinderInfo::File(FinderInfoFile::from_slice(buf)?),
+            FinderInfoKind::Directory => FinderInfo::Directory(FinderInfoFolder::from_slice(buf)?),
+        })
+    }
+
+    /// Checks that the reserved fields are zero. `Unknown` FinderInfo is always accepted.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            FinderInfo::File(fi) => fi.validate(),
+            FinderInfo::Directory(fi) => fi.validate(),
+            FinderInfo::Unknown(_) => Ok(()),
+        }
+    }
+
+    /// Interprets FinderInfo as laid out for the given kind of object.
+    pub const fn from_bytes_as(kind: FinderInfoKind, buf: &[u8; 32]) -> FinderInfo {
+        match kind {
+            FinderInfoKind::File => FinderInfo::File(FinderInfoFile::from_bytes(buf)),
+            FinderInfoKind::Directory => FinderInfo::Directory(FinderInfoFolder::from_bytes(buf)),
+        }
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 32] {
+        match self {
+            FinderInfo::File(fi) => fi.to_bytes(),
+            FinderInfo::Directory(fi) => fi.to_bytes(),
+            FinderInfo::Unknown(buf) => *buf,
+        }
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
+
+    /// Returns the kind of object this FinderInfo describes, or `None` if it is `Unknown`.
+    pub fn kind(&self) -> Option<FinderInfoKind> {
+        match self {
+            FinderInfo::File(_) => Some(FinderInfoKind::File),
+            FinderInfo::Directory(_) => Some(FinderInfoKind::Directory),
+            FinderInfo::Unknown(_) => None,
+        }
+    }
+
+    /// Reinterprets `Unknown` FinderInfo as the given kind. Known FinderInfo is returned unchanged,
+    /// even if it is of a different kind.
+    pub fn resolve(self, kind: FinderInfoKind) -> FinderInfo {
+        match self {
+            FinderInfo::Unknown(buf) => FinderInfo::from_bytes_as(kind, &buf),
+            known => known,
+        }
+    }
+
+    pub fn as_file(&self) -> Option<&FinderInfoFile> {
+        match self {
+            FinderInfo::File(fi) => Some(fi),
+            _ => None,
+        }
+    }
+
+    pub fn as_file_mut(&mut self) -> Option<&mut FinderInfoFile> {
+        match self {
+            FinderInfo::File(fi) => Some(fi),
+            _ => None,
+        }
+    }
+
+    pub fn as_folder(&self) -> Option<&FinderInfoFolder> {
+        match self {
+            FinderInfo::Directory(fi) => Some(fi),
+            _ => None,
+        }
+    }
+
+    pub fn as_folder_mut(&mut self) -> Option<&mut FinderInfoFolder> {
+        match self {
+            FinderInfo::Directory(fi) => Some(fi),
+            _ => None,
+        }
+    }
+}
+
+impl From<FinderInfoFile> for FinderInfo {
+    fn from(fi: FinderInfoFile) -> FinderInfo {
+        FinderInfo::File(fi)
+    }
+}
+
+impl From<FinderInfoFolder> for FinderInfo {
+    fn from(fi: FinderInfoFolder) -> FinderInfo {
+        FinderInfo::Directory(fi)
+    }
+}
+
+/// `Unknown` FinderInfo converts by reinterpreting its bytes; `Directory` FinderInfo is handed back
+/// unchanged as the error.
+impl TryFrom<FinderInfo> for FinderInfoFile {
+    type Error = FinderInfo;
+
+    fn try_from(fi: FinderInfo) -> Result<FinderInfoFile, FinderInfo> {
+        match fi.resolve(FinderInfoKind::File) {
+            FinderInfo::File(fi) => Ok(fi),
+            other => Err(other),
+        }
+    }
+}
+
+/// `Unknown` FinderInfo converts by reinterpreting its bytes; `File` FinderInfo is handed back
+/// unchanged as the error.
+impl TryFrom<FinderInfo> for FinderInfoFolder {
+    type Error = FinderInfo;
+
+    fn try_from(fi: FinderInfo) -> Result<FinderInfoFolder, FinderInfo> {
+        match fi.resolve(FinderInfoKind::Directory) {
+            FinderInfo::Directory(fi) => Ok(fi),
+            other => Err(other),
+        }
     }
 }
 
@@ -822,4 +1983,296 @@ mod tests {
         assert!(!finfo.folder_info.finderFlags.has_custom_icon());
         assert_eq!(finfo.folder_info.finderFlags.color(), Some(LabelColor::Red));
     }
+
+    #[test]
+    fn test_finderinfo_enum() {
+        let fi =
+            FinderInfo::read(&mut io::Cursor::new(FINDERINFO_XATTR_FOO_BLUE_RED_ICON)).unwrap();
+        assert_eq!(fi.kind(), None);
+
+        let mut cursor = io::Cursor::new(vec![]);
+        fi.write(&mut cursor).unwrap();
+        assert_eq!(cursor.into_inner(), FINDERINFO_XATTR_FOO_BLUE_RED_ICON);
+
+        let file = fi.resolve(FinderInfoKind::File);
+        assert_eq!(file.kind(), Some(FinderInfoKind::File));
+        assert_eq!(
+            file.as_file().unwrap().file_info.finderFlags.color(),
+            Some(LabelColor::Red)
+        );
+        // Already-resolved FinderInfo keeps its kind.
+        assert_eq!(file.resolve(FinderInfoKind::Directory), file);
+
+        let folder = FinderInfo::read_as(
+            FinderInfoKind::Directory,
+            &mut io::Cursor::new(FINDERINFO_XATTR_FOO_BLUE_RED_ICON),
+        )
+        .unwrap();
+        assert!(folder
+            .as_folder()
+            .unwrap()
+            .folder_info
+            .finderFlags
+            .has_custom_icon());
+        assert_eq!(FinderInfoFile::try_from(folder), Err(folder));
+        assert!(FinderInfoFolder::try_from(folder).is_ok());
+        assert_eq!(FinderInfoFile::try_from(fi).map(FinderInfo::from), Ok(file));
+    }
+
+    #[test]
+    fn test_strict_lengths() {
+        match FinderInfoFile::read(&mut io::Cursor::new(&FINDERINFO_XATTR_VALUE_ON[..20])) {
+            Err(Error::Truncated { offset: 20 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match FinderInfoFolder::from_slice(&FINDERINFO_XATTR_VALUE_ON[..31]) {
+            Err(Error::Truncated { offset: 31 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut long = FINDERINFO_XATTR_VALUE_ON.to_vec();
+        long.extend_from_slice(&[0, 0, 0]);
+        match FinderInfoFile::from_slice(&long) {
+            Err(Error::TrailingBytes { count: 3 }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        let finfo = FinderInfoFile::from_slice(&FINDERINFO_XATTR_VALUE_ON).unwrap();
+        assert!(finfo.file_info.finderFlags.has_custom_icon());
+    }
+
+    #[test]
+    fn test_validate_reserved() {
+        let mut finfo = FinderInfoFolder::default();
+        assert!(finfo.validate().is_ok());
+        finfo.extended_folder_info.reserved2 = 1;
+        match finfo.validate() {
+            Err(Error::ReservedField { field: "reserved2" }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(FinderInfo::Unknown([0xff; 32]).validate().is_ok());
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        const FINFO: FinderInfoFile =
+            FinderInfoFile::from_bytes(&FINDERINFO_XATTR_FOO_BLUE_RED_ICON);
+        assert_eq!(FINFO.file_info.finderFlags.color(), Some(LabelColor::Red));
+        assert_eq!(FINFO.to_bytes(), FINDERINFO_XATTR_FOO_BLUE_RED_ICON);
+
+        let mut buf = [0u8; 32];
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = i as u8 + 1;
+        }
+        let finfo = FinderInfoFile::from(buf);
+        assert_eq!(finfo.file_info.fileType, OSType([1, 2, 3, 4]));
+        assert_eq!(finfo.extended_file_info.putAwayFolderID, 0x1d1e1f20);
+        assert_eq!(<[u8; 32]>::from(finfo), buf);
+        let folder = FinderInfoFolder::from(buf);
+        assert_eq!(folder.folder_info.windowBounds.right, 0x0708);
+        assert_eq!(folder.to_bytes(), buf);
+        assert_eq!(
+            FinderInfo::from_bytes_as(FinderInfoKind::Directory, &buf).to_bytes(),
+            buf
+        );
+
+        let mut cursor = io::Cursor::new(vec![]);
+        folder.write(&mut cursor).unwrap();
+        assert_eq!(cursor.into_inner(), buf.to_vec());
+
+        let half = ExtendedFolderInfo::try_from(&buf[16..]).unwrap();
+        assert_eq!(half, folder.extended_folder_info);
+        assert_eq!(
+            FileInfo::try_from(&buf[..16]).unwrap().to_bytes()[..],
+            buf[..16]
+        );
+        match FolderInfo::try_from(&buf[..]) {
+            Err(Error::InvalidLength {
+                expected: 16,
+                actual: 32,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        match FinderInfoFolder::try_from(&buf[..16]) {
+            Err(Error::InvalidLength {
+                expected: 32,
+                actual: 16,
+            }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_extended_finder_flags() {
+        let mut flags = ExtendedFinderFlags::from(0x0284);
+        assert!(flags.has_routing_info());
+        assert!(flags.is_busy());
+        assert_eq!(flags.unknown_bits(), 0x0200);
+        assert_eq!(
+            format!("{:?}", flags),
+            "ExtendedFinderFlags { raw: 644, flags: [\"kExtendedFlagObjectIsBusy\", \"kExtendedFlagHasRoutingInfo\"] }"
+        );
+
+        flags.set_is_busy(true);
+        flags.set_has_custom_badge(true);
+        flags.set_has_routing_info(false);
+        flags.set_are_invalid(true);
+        assert_eq!(u16::from(flags), 0x8380);
+
+        flags.set_is_busy(false);
+        flags.clear_unknown_bits();
+        assert_eq!(u16::from(flags), 0x8100);
+
+        flags.set_bits(0x0202, true);
+        assert!(flags.contains_bits(0x0002));
+        assert_eq!(flags.unknown_bits(), 0x0202);
+        flags.set_bits(0x8002, false);
+        assert_eq!(flags.bits(), 0x0300);
+    }
+
+    #[test]
+    fn test_date_added_and_document_id() {
+        // FinderInfo of a file as written by macOS, with a document ID of 0x1234 and a date added
+        // of 2021-08-06T00:00:00Z.
+        let mut buf = [0u8; 32];
+        buf[16..24].copy_from_slice(&[0x00, 0x00, 0x12, 0x34, 0x61, 0x0c, 0x7b, 0x80]);
+        let mut finfo = FinderInfoFile::from_bytes(&buf);
+        let xinfo = &mut finfo.extended_file_info;
+        assert_eq!(xinfo.document_id(), Some(0x1234));
+        assert_eq!(
+            xinfo.date_added(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_628_208_000))
+        );
+
+        xinfo.extendedFinderFlags.set_are_invalid(true);
+        assert_eq!(xinfo.document_id(), None);
+        assert_eq!(xinfo.date_added(), None);
+
+        let added = UNIX_EPOCH + Duration::from_secs(0x8000_0001);
+        xinfo.set_date_added(Some(added)).unwrap();
+        assert!(!xinfo.extendedFinderFlags.are_invalid());
+        assert_eq!(xinfo.date_added(), Some(added));
+        assert_eq!(xinfo.reserved1, [0x0000, 0x1234, -0x8000, 0x0001]);
+        xinfo.set_document_id(None);
+        assert_eq!(xinfo.document_id(), None);
+        match xinfo.set_date_added(Some(UNIX_EPOCH - Duration::from_secs(1))) {
+            Err(Error::DateOutOfRange) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let mut folder = FinderInfoFolder::from_bytes(&buf);
+        let xinfo = &mut folder.extended_folder_info;
+        assert_eq!(xinfo.document_id(), Some(0x1234));
+        assert_eq!(xinfo.scrollPosition, Point { v: 0, h: 0x1234 });
+        xinfo.set_document_id(Some(0xfffe_0001));
+        xinfo.set_date_added(None).unwrap();
+        assert_eq!(xinfo.document_id(), Some(0xfffe_0001));
+        assert_eq!(xinfo.date_added(), None);
+        assert_eq!(
+            &folder.to_bytes()[16..24],
+            &[0xff, 0xfe, 0x00, 0x01, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_finder_flags_set_operations() {
+        let mut flags: FinderFlags =
+            "Red | kIsInvisible | kHideExtension | kHasCustomIcon | 0x0200"
+                .parse()
+                .unwrap();
+        assert_eq!(flags.bits(), 0x461c | 0x0200);
+        assert_eq!(flags.color(), Some(LabelColor::Red));
+        assert_eq!(flags.unknown_bits(), 0x0200);
+        assert_eq!(
+            flags.iter().collect::<Vec<_>>(),
+            vec![Flag::HideExtension, Flag::HasCustomIcon, Flag::IsInvisible]
+        );
+        assert_eq!(
+            flags.to_string(),
+            "Red | kHideExtension | kHasCustomIcon | kIsInvisible | 0x0200"
+        );
+        assert_eq!(flags.to_string().parse::<FinderFlags>().unwrap(), flags);
+
+        let kept = flags & (FinderFlags::COLOR | Flag::HideExtension);
+        assert_eq!(kept.to_string(), "Red | kHideExtension");
+        assert_eq!(
+            flags & !FinderFlags::COLOR & !Flag::IsInvisible,
+            "kHideExtension | kHasCustomIcon | 0x0200".parse().unwrap()
+        );
+
+        flags.toggle(Flag::IsInvisible);
+        flags.insert(Flag::IsOnDesk);
+        flags.remove(Flag::HasCustomIcon);
+        assert!(flags.is_on_desk());
+        assert!(!flags.contains(Flag::IsInvisible));
+        assert_eq!(
+            Flag::IsAlias | Flag::IsStationery,
+            FinderFlags::from(0x8800)
+        );
+        assert_eq!(FinderFlags::empty().to_string(), "0");
+        assert!("0".parse::<FinderFlags>().unwrap().is_empty());
+        assert!("kIsBogus".parse::<FinderFlags>().is_err());
+    }
+
+    #[test]
+    fn test_ostype() {
+        const TEXT: OSType = OSType::new(b"TEXT");
+        assert_eq!(TEXT.to_u32(), 0x54455854);
+        assert_eq!(OSType::from(0x54455854), TEXT);
+        assert_eq!(u32::from(TEXT), 0x54455854);
+        assert_eq!(TEXT.to_string(), "TEXT");
+        assert_eq!(format!("{:?}", TEXT), "'TEXT'");
+        for s in &["TEXT", "'TEXT'", "0x54455854", "\\x54EXT"] {
+            assert_eq!(s.parse::<OSType>().unwrap(), TEXT);
+        }
+
+        let ftp = OSType([0xa9, b'F', b'T', b'P']);
+        assert_eq!(ftp.to_string(), "©FTP");
+        assert_eq!("©FTP".parse::<OSType>().unwrap(), ftp);
+        assert!(ftp.is_printable());
+
+        let odd = OSType([0x00, b'\\', b'\'', 0x7f]);
+        assert_eq!(odd.to_string(), "\\x00\\\\'\\x7F");
+        assert_eq!(odd.to_string().parse::<OSType>().unwrap(), odd);
+        assert!(!odd.is_printable());
+
+        // Quotes are only stripped when the quoted string isn't itself a code.
+        assert_eq!("'ab'".parse::<OSType>().unwrap(), OSType::new(b"'ab'"));
+        assert_eq!("0x12".parse::<OSType>().unwrap(), OSType::new(b"0x12"));
+        assert_eq!("'PDF '".parse::<OSType>().unwrap(), OSType::new(b"PDF "));
+
+        assert!(OSType::default().is_zero());
+        assert!(!TEXT.is_zero());
+        for s in &["TEX", "TEXTS", "0x5445585", "日本語字", "\\q123"] {
+            assert!(s.parse::<OSType>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_object_kind() {
+        let mut file = FinderInfoFile::default();
+        assert_eq!(file.kind(), ObjectKind::File);
+        file.file_info.finderFlags.set_has_bundle(true);
+        assert_eq!(file.kind(), ObjectKind::Bundle);
+        file.file_info.finderFlags.set_is_stationery(true);
+        assert_eq!(file.kind(), ObjectKind::Stationery);
+        file.file_info.finderFlags.set_is_alias(true);
+        assert_eq!(file.kind(), ObjectKind::Alias);
+
+        let kinds = [
+            (b"slnk", b"rhap", ObjectKind::SymbolicLink),
+            (b"hlnk", b"hfs+", ObjectKind::HardLink),
+            (b"fdrp", b"MACS", ObjectKind::DirectoryHardLink),
+            (b"brok", b"MACS", ObjectKind::CopyInProgress),
+            (b"slnk", b"MACS", ObjectKind::Alias),
+        ];
+        for &(file_type, creator, kind) in &kinds {
+            file.file_info.fileType = OSType::new(file_type);
+            file.file_info.fileCreator = OSType::new(creator);
+            assert_eq!(file.kind(), kind);
+        }
+
+        let mut folder = FinderInfoFolder::default();
+        assert_eq!(folder.kind(), ObjectKind::Folder);
+        folder.folder_info.finderFlags.set_has_bundle(true);
+        assert_eq!(folder.kind(), ObjectKind::Package);
+    }
 }
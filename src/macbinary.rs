@@ -0,0 +1,403 @@
+//! MacBinary, the format classic Mac files were downloaded in: a 128 byte header holding the name
+//! and Finder information, followed by the data fork and the resource fork, each padded to a
+//! multiple of 128 bytes.
+//!
+//! All three versions are read. MacBinary I has no checksum and only the high byte of the Finder
+//! flags; MacBinary II adds the low byte, a CRC of the header and the Get Info comment; MacBinary
+//! III adds a signature and the script and extended flags. Files are always written as MacBinary
+//! III.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::time::SystemTime;
+
+use crate::crc::crc16;
+use crate::{
+    be_u16, be_u32, mac_time_from_u32, mac_time_to_u32, macroman, Error, FinderInfoFile, Point,
+    Result,
+};
+
+pub const HEADER_LEN: usize = 128;
+/// The signature of a MacBinary III header, 'mBIN'.
+pub const SIGNATURE: [u8; 4] = *b"mBIN";
+const MAX_NAME_LEN: usize = 63;
+/// Version written to byte 122 by MacBinary II and III writers.
+const VERSION_II: u8 = 129;
+const VERSION_III: u8 = 130;
+
+/// A version of the MacBinary format.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Version {
+    MacBinaryI,
+    MacBinaryII,
+    MacBinaryIII,
+}
+
+/// Rounds `len` up to the 128 byte blocks MacBinary pads everything to.
+fn padded(len: usize) -> usize {
+    (len + HEADER_LEN - 1) & !(HEADER_LEN - 1)
+}
+
+/// A decoded MacBinary header.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Header {
+    pub version: Version,
+    /// The file name, decoded from Mac OS Roman.
+    pub name: String,
+    /// Type, creator, Finder flags, location and folder ID, plus the script and extended flags for
+    /// MacBinary III. The rest of the extended information isn't stored.
+    pub finder_info: FinderInfoFile,
+    /// The file is locked.
+    pub protected: bool,
+    pub data_fork_len: u32,
+    pub resource_fork_len: u32,
+    /// Creation date, in seconds since 1904. See `created_time`.
+    pub created: u32,
+    /// Modification date, in seconds since 1904. See `modified_time`.
+    pub modified: u32,
+    pub comment_len: u16,
+    pub secondary_header_len: u16,
+}
+
+impl Header {
+    /// Decodes a header, working out which version of MacBinary wrote it.
+    ///
+    /// A header whose CRC matches is MacBinary II, or III if it also has the signature. Otherwise
+    /// it has to look like MacBinary I, with the fields added later all zero.
+    pub fn from_bytes(buf: &[u8; HEADER_LEN]) -> Result<Header> {
+        let name_len = usize::from(buf[1]);
+        if buf[0] != 0 || buf[74] != 0 || name_len == 0 || name_len > MAX_NAME_LEN {
+            return Err(Error::Malformed {
+                reason: "not a MacBinary header",
+            });
+        }
+        let expected = be_u16(buf, 124);
+        let actual = crc16(&buf[..124]);
+        let version = if expected == actual {
+            if buf[123] > VERSION_III {
+                return Err(Error::UnsupportedVersion {
+                    version: u32::from(buf[123]),
+                });
+            }
+            if buf[102..106] == SIGNATURE {
+                Version::MacBinaryIII
+            } else {
+                Version::MacBinaryII
+            }
+        } else if buf[82] == 0 && buf[99..].iter().all(|&b| b == 0) {
+            Version::MacBinaryI
+        } else {
+            return Err(Error::ChecksumMismatch { expected, actual });
+        };
+
+        let mut finder_info = FinderInfoFile::default();
+        let info = &mut finder_info.file_info;
+        info.fileType.0.copy_from_slice(&buf[65..69]);
+        info.fileCreator.0.copy_from_slice(&buf[69..73]);
+        let low_flags = if version >= Version::MacBinaryII {
+            buf[101]
+        } else {
+            0
+        };
+        info.finderFlags = u16::from_be_bytes([buf[73], low_flags]).into();
+        info.location = Point {
+            v: be_u16(buf, 75) as i16,
+            h: be_u16(buf, 77) as i16,
+        };
+        info.reservedField = be_u16(buf, 79);
+        if version == Version::MacBinaryIII {
+            finder_info.extended_file_info.extendedFinderFlags =
+                u16::from_be_bytes([buf[106], buf[107]]).into();
+        }
+
+        let (comment_len, secondary_header_len) = if version >= Version::MacBinaryII {
+            (be_u16(buf, 99), be_u16(buf, 120))
+        } else {
+            (0, 0)
+        };
+        Ok(Header {
+            version,
+            name: macroman::decode(&buf[2..2 + name_len]),
+            finder_info,
+            protected: buf[81] & 0x01 != 0,
+            data_fork_len: be_u32(buf, 83),
+            resource_fork_len: be_u32(buf, 87),
+            created: be_u32(buf, 91),
+            modified: be_u32(buf, 95),
+            comment_len,
+            secondary_header_len,
+        })
+    }
+
+    /// Encodes the header as MacBinary III, whatever `version` says.
+    pub fn to_bytes(&self) -> Result<[u8; HEADER_LEN]> {
+        let name = macroman::encode(&self.name)
+            .filter(|name| !name.is_empty() && name.len() <= MAX_NAME_LEN)
+            .ok_or_else(|| Error::Parse {
+                input: self.name.clone(),
+                expected: "a Mac OS Roman file name of 1 to 63 bytes",
+            })?;
+        let info = &self.finder_info.file_info;
+        let flags = info.finderFlags.bits().to_be_bytes();
+        let extended_flags =
+            u16::from(self.finder_info.extended_file_info.extendedFinderFlags).to_be_bytes();
+
+        let mut buf = [0u8; HEADER_LEN];
+        buf[1] = name.len() as u8;
+        buf[2..2 + name.len()].copy_from_slice(&name);
+        buf[65..69].copy_from_slice(&info.fileType.0);
+        buf[69..73].copy_from_slice(&info.fileCreator.0);
+        buf[73] = flags[0];
+        buf[75..77].copy_from_slice(&info.location.v.to_be_bytes());
+        buf[77..79].copy_from_slice(&info.location.h.to_be_bytes());
+        buf[79..81].copy_from_slice(&info.reservedField.to_be_bytes());
+        buf[81] = u8::from(self.protected);
+        buf[83..87].copy_from_slice(&self.data_fork_len.to_be_bytes());
+        buf[87..91].copy_from_slice(&self.resource_fork_len.to_be_bytes());
+        buf[91..95].copy_from_slice(&self.created.to_be_bytes());
+        buf[95..99].copy_from_slice(&self.modified.to_be_bytes());
+        buf[99..101].copy_from_slice(&self.comment_len.to_be_bytes());
+        buf[101] = flags[1];
+        buf[102..106].copy_from_slice(&SIGNATURE);
+        buf[106] = extended_flags[0];
+        buf[107] = extended_flags[1];
+        buf[120..122].copy_from_slice(&self.secondary_header_len.to_be_bytes());
+        buf[122] = VERSION_III;
+        buf[123] = VERSION_II;
+        let crc = crc16(&buf[..124]);
+        buf[124..126].copy_from_slice(&crc.to_be_bytes());
+        Ok(buf)
+    }
+
+    /// The creation date, or `None` if it isn't set. Treated as UTC, although classic Mac OS
+    /// wrote local time.
+    pub fn created_time(&self) -> Option<SystemTime> {
+        Some(self.created)
+            .filter(|&t| t != 0)
+            .map(mac_time_from_u32)
+    }
+
+    pub fn set_created_time(&mut self, time: Option<SystemTime>) -> Result<()> {
+        self.created = match time {
+            Some(time) => mac_time_to_u32(time).ok_or(Error::DateOutOfRange)?,
+            None => 0,
+        };
+        Ok(())
+    }
+
+    /// The modification date, or `None` if it isn't set.
+    pub fn modified_time(&self) -> Option<SystemTime> {
+        Some(self.modified)
+            .filter(|&t| t != 0)
+            .map(mac_time_from_u32)
+    }
+
+    pub fn set_modified_time(&mut self, time: Option<SystemTime>) -> Result<()> {
+        self.modified = match time {
+            Some(time) => mac_time_to_u32(time).ok_or(Error::DateOutOfRange)?,
+            None => 0,
+        };
+        Ok(())
+    }
+}
+
+/// Returns the MacBinary version of `buf`, or `None` if it doesn't start with a MacBinary header.
+pub fn detect(buf: &[u8]) -> Option<Version> {
+    let header = buf.get(..HEADER_LEN)?;
+    let header = <&[u8; HEADER_LEN]>::try_from(header).ok()?;
+    Header::from_bytes(header).ok().map(|h| h.version)
+}
+
+/// A complete MacBinary file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MacBinary {
+    /// The header. Its fork and comment lengths are ignored when writing and taken from the
+    /// fields below instead.
+    pub header: Header,
+    pub data_fork: Vec<u8>,
+    pub resource_fork: Vec<u8>,
+    /// The Get Info comment, in Mac OS Roman.
+    pub comment: Vec<u8>,
+}
+
+impl MacBinary {
+    pub fn new(
+        name: &str,
+        finder_info: FinderInfoFile,
+        data_fork: Vec<u8>,
+        resource_fork: Vec<u8>,
+    ) -> MacBinary {
+        MacBinary {
+            header: Header {
+                version: Version::MacBinaryIII,
+                name: name.to_string(),
+                finder_info,
+                protected: false,
+                data_fork_len: 0,
+                resource_fork_len: 0,
+                created: 0,
+                modified: 0,
+                comment_len: 0,
+                secondary_header_len: 0,
+            },
+            data_fork,
+            resource_fork,
+            comment: Vec::new(),
+        }
+    }
+
+    /// Reads a MacBinary file to the end of `r`.
+    pub fn read<R: Read>(r: &mut R) -> Result<MacBinary> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        MacBinary::from_slice(&buf)
+    }
+
+    /// Parses a MacBinary file. The padding after the last part may be missing, as some tools
+    /// strip it.
+    pub fn from_slice(buf: &[u8]) -> Result<MacBinary> {
+        let header = buf
+            .get(..HEADER_LEN)
+            .ok_or(Error::Truncated { offset: buf.len() })?;
+        let header = Header::from_bytes(<&[u8; HEADER_LEN]>::try_from(header).unwrap())?;
+
+        let mut offset = HEADER_LEN + padded(usize::from(header.secondary_header_len));
+        let mut take = |len: usize| -> Result<Vec<u8>> {
+            if len == 0 {
+                return Ok(Vec::new());
+            }
+            let part = buf
+                .get(offset..offset + len)
+                .ok_or(Error::Truncated { offset: buf.len() })?;
+            offset += padded(len);
+            Ok(part.to_vec())
+        };
+        let data_fork = take(header.data_fork_len as usize)?;
+        let resource_fork = take(header.resource_fork_len as usize)?;
+        let comment = take(usize::from(header.comment_len))?;
+        Ok(MacBinary {
+            header,
+            data_fork,
+            resource_fork,
+            comment,
+        })
+    }
+
+    /// Encodes the file as MacBinary III.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let len = |field: &'static str, part: &[u8], max: usize| {
+            if part.len() > max {
+                Err(Error::TooLarge {
+                    field,
+                    max,
+                    actual: part.len(),
+                })
+            } else {
+                Ok(part.len())
+            }
+        };
+        let mut header = self.header.clone();
+        header.data_fork_len = len("data fork length", &self.data_fork, u32::MAX as usize)? as u32;
+        header.resource_fork_len = len(
+            "resource fork length",
+            &self.resource_fork,
+            u32::MAX as usize,
+        )? as u32;
+        header.comment_len = len("comment length", &self.comment, usize::from(u16::MAX))? as u16;
+        header.secondary_header_len = 0;
+        header.version = Version::MacBinaryIII;
+
+        let mut buf = header.to_bytes()?.to_vec();
+        for part in &[&self.data_fork, &self.resource_fork, &self.comment] {
+            buf.extend_from_slice(part);
+            buf.resize(padded(buf.len()), 0);
+        }
+        Ok(buf)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LabelColor, OSType};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_macbinary_roundtrip() {
+        let mut finder_info = FinderInfoFile::default();
+        let info = &mut finder_info.file_info;
+        info.fileType = OSType::new(b"TEXT");
+        info.fileCreator = OSType::new(b"R*ch");
+        info.finderFlags.set_color(Some(LabelColor::Red));
+        info.finderFlags.set_is_invisible(true);
+        info.location = Point { v: 10, h: -20 };
+        info.reservedField = 7;
+        let mut file = MacBinary::new("Read Me ©", finder_info, b"hello".to_vec(), vec![1; 200]);
+        file.comment = b"notes".to_vec();
+        let modified = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        file.header.set_modified_time(Some(modified)).unwrap();
+
+        let buf = file.to_bytes().unwrap();
+        assert_eq!(buf.len(), 128 + 128 + 256 + 128);
+        assert_eq!(&buf[102..106], b"mBIN");
+        assert_eq!(buf[10], 0xa9);
+        assert_eq!(detect(&buf), Some(Version::MacBinaryIII));
+
+        let read = MacBinary::read(&mut buf.as_slice()).unwrap();
+        assert_eq!(read.header.finder_info, finder_info);
+        assert_eq!(read.header.name, "Read Me ©");
+        assert_eq!(read.header.modified_time(), Some(modified));
+        assert_eq!(read.header.created_time(), None);
+        assert_eq!(read.data_fork, b"hello");
+        assert_eq!(read.resource_fork, vec![1; 200]);
+        assert_eq!(read.comment, b"notes");
+        assert_eq!(read.to_bytes().unwrap(), buf);
+
+        // Without the trailing padding.
+        assert_eq!(
+            MacBinary::from_slice(&buf[..128 + 128 + 256 + 5]).unwrap(),
+            read
+        );
+
+        let mut corrupt = buf.clone();
+        corrupt[66] ^= 0x20;
+        match MacBinary::from_slice(&corrupt) {
+            Err(Error::ChecksumMismatch { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(detect(&corrupt), None);
+        assert_eq!(detect(b"not macbinary"), None);
+    }
+
+    #[test]
+    fn test_macbinary_versions() {
+        let mut header = [0u8; HEADER_LEN];
+        header[1] = 4;
+        header[2..6].copy_from_slice(b"File");
+        header[65..73].copy_from_slice(b"APPLMOSS");
+        header[73] = 0x20;
+        header[86] = 3;
+        let mut buf = header.to_vec();
+        buf.extend_from_slice(b"abc");
+
+        let file = MacBinary::from_slice(&buf).unwrap();
+        assert_eq!(file.header.version, Version::MacBinaryI);
+        assert!(file.header.finder_info.file_info.finderFlags.has_bundle());
+        assert_eq!(file.data_fork, b"abc");
+
+        // Adding the low flags byte makes it MacBinary II, which needs a CRC.
+        header[101] = 0x01;
+        assert!(Header::from_bytes(&header).is_err());
+        header[122] = VERSION_II;
+        header[123] = VERSION_II;
+        let crc = crc16(&header[..124]);
+        header[124..126].copy_from_slice(&crc.to_be_bytes());
+        let decoded = Header::from_bytes(&header).unwrap();
+        assert_eq!(decoded.version, Version::MacBinaryII);
+        assert!(decoded.finder_info.file_info.finderFlags.is_on_desk());
+    }
+}
@@ -0,0 +1,59 @@
+//! Conversion between Mac OS Roman, the character set of classic Mac OS, and Unicode.
+//!
+//! The lower half is ASCII. Every byte maps to exactly one character and back, so strings
+//! round-trip losslessly as long as they only contain characters from the table.
+
+/// Characters for bytes 0x80 to 0xff.
+const HIGH: [char; 128] = [
+    'Ä', 'Å', 'Ç', 'É', 'Ñ', 'Ö', 'Ü', 'á', 'à', 'â', 'ä', 'ã', 'å', 'ç', 'é', 'è', //
+    'ê', 'ë', 'í', 'ì', 'î', 'ï', 'ñ', 'ó', 'ò', 'ô', 'ö', 'õ', 'ú', 'ù', 'û', 'ü', //
+    '†', '°', '¢', '£', '§', '•', '¶', 'ß', '®', '©', '™', '´', '¨', '≠', 'Æ', 'Ø', //
+    '∞', '±', '≤', '≥', '¥', 'µ', '∂', '∑', '∏', 'π', '∫', 'ª', 'º', 'Ω', 'æ', 'ø', //
+    '¿', '¡', '¬', '√', 'ƒ', '≈', '∆', '«', '»', '…', '\u{a0}', 'À', 'Ã', 'Õ', 'Œ', 'œ', //
+    '–', '—', '“', '”', '‘', '’', '÷', '◊', 'ÿ', 'Ÿ', '⁄', '€', '‹', '›', 'ﬁ', 'ﬂ', //
+    '‡', '·', '‚', '„', '‰', 'Â', 'Ê', 'Á', 'Ë', 'È', 'Í', 'Î', 'Ï', 'Ì', 'Ó', 'Ô', //
+    '\u{f8ff}', 'Ò', 'Ú', 'Û', 'Ù', 'ı', 'ˆ', '˜', '¯', '˘', '˙', '˚', '¸', '˝', '˛', 'ˇ', //
+];
+
+pub(crate) fn decode_byte(b: u8) -> char {
+    if b < 0x80 {
+        b as char
+    } else {
+        HIGH[usize::from(b - 0x80)]
+    }
+}
+
+pub(crate) fn encode_char(c: char) -> Option<u8> {
+    if c.is_ascii() {
+        return Some(c as u8);
+    }
+    HIGH.iter().position(|&h| h == c).map(|i| 0x80 + i as u8)
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| decode_byte(b)).collect()
+}
+
+/// Encodes `s`, or returns `None` if it contains a character Mac OS Roman can't represent.
+pub(crate) fn encode(s: &str) -> Option<Vec<u8>> {
+    s.chars().map(encode_char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macroman_roundtrip() {
+        for b in 0..=255u8 {
+            assert_eq!(encode_char(decode_byte(b)), Some(b));
+        }
+        assert_eq!(decode_byte(0xa9), '©');
+        assert_eq!(encode_char('™'), Some(0xaa));
+        assert_eq!(encode_char('日'), None);
+        assert_eq!(
+            encode("Café™").map(|b| decode(&b)),
+            Some("Café™".to_string())
+        );
+    }
+}
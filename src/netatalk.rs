@@ -0,0 +1,568 @@
+//! Netatalk 3 metadata, stored by the AFP server in the `user.org.netatalk.Metadata` extended
+//! attribute of every file and folder it shares.
+//!
+//! The attribute is an AppleDouble version 2 header with a fixed layout: FinderInfo, a comment, file
+//! dates, AFP file info and four private entries netatalk uses to track the file's CNID. Netatalk
+//! reads the entries from their fixed offsets, so `Metadata` edits the blob in place rather than
+//! re-encoding it with `AppleDouble::to_bytes`.
+//!
+//! Netatalk 2 kept the same kind of header in a file of its own instead, inside a hidden
+//! `.AppleDouble` directory next to the files it describes; see `AppleDoubleDirStore`.
+
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::appledouble::{AppleDouble, EntryId, FileDates, MAGIC, VERSION_2};
+use crate::store::FinderInfoStore;
+use crate::{be_u32, kind_of_path, Error, FinderInfo, FinderInfoKind, Result};
+
+/// Name of the attribute on Linux.
+pub const ATTRIBUTE_NAME: &str = "user.org.netatalk.Metadata";
+
+/// The AppleDouble filler netatalk writes.
+pub const FILLER: [u8; 16] = *b"Netatalk        ";
+
+/// Device number of the file when its CNID was last looked up.
+pub const PRIVATE_DEV: EntryId = EntryId(0x8044_4556);
+/// Inode number of the file when its CNID was last looked up.
+pub const PRIVATE_INO: EntryId = EntryId(0x8049_4e4f);
+/// Stamp of the CNID database the CNID came from.
+pub const PRIVATE_SYN: EntryId = EntryId(0x8053_594e);
+/// The file's CNID, a hint for the CNID database.
+pub const PRIVATE_ID: EntryId = EntryId(0x8053_567e);
+
+/// The entries netatalk creates, with the space it reserves for each. The comment has room for 200
+/// bytes but starts out empty.
+const LAYOUT: [(EntryId, u32, u32); 8] = [
+    (EntryId::FINDER_INFO, 32, 32),
+    (EntryId::COMMENT, 200, 0),
+    (EntryId::FILE_DATES, 16, 16),
+    (EntryId::AFP_INFO, 4, 4),
+    (PRIVATE_DEV, 8, 8),
+    (PRIVATE_INO, 8, 8),
+    (PRIVATE_SYN, 8, 8),
+    (PRIVATE_ID, 4, 4),
+];
+
+const HEADER_LEN: usize = 26;
+const DESCRIPTOR_LEN: usize = 12;
+
+/// The contents of a `user.org.netatalk.Metadata` attribute.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Metadata {
+    buf: Vec<u8>,
+    double: AppleDouble,
+}
+
+impl Default for Metadata {
+    fn default() -> Metadata {
+        Metadata::new()
+    }
+}
+
+impl Metadata {
+    /// Creates metadata laid out as netatalk does for a new file, with zero FinderInfo, unknown
+    /// dates and no CNID.
+    pub fn new() -> Metadata {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&MAGIC.to_be_bytes());
+        buf.extend_from_slice(&VERSION_2.to_be_bytes());
+        buf.extend_from_slice(&FILLER);
+        buf.extend_from_slice(&(LAYOUT.len() as u16).to_be_bytes());
+        let mut offset = (HEADER_LEN + LAYOUT.len() * DESCRIPTOR_LEN) as u32;
+        for &(id, reserved, length) in &LAYOUT {
+            buf.extend_from_slice(&id.0.to_be_bytes());
+            buf.extend_from_slice(&offset.to_be_bytes());
+            buf.extend_from_slice(&length.to_be_bytes());
+            offset += reserved;
+        }
+        buf.resize(offset as usize, 0);
+        let mut metadata = Metadata::from_slice(&buf).expect("new metadata is valid");
+        metadata.patch(EntryId::FILE_DATES, &FileDates::default().to_bytes());
+        metadata
+    }
+
+    /// Parses the attribute. It must have a FinderInfo entry of at least 32 bytes.
+    pub fn from_slice(buf: &[u8]) -> Result<Metadata> {
+        let double = AppleDouble::from_slice(buf)?;
+        match double.entry(EntryId::FINDER_INFO) {
+            Some(data) if data.len() >= 32 => {}
+            Some(data) => return Err(Error::Truncated { offset: data.len() }),
+            None => {
+                return Err(Error::Malformed {
+                    reason: "netatalk metadata has no FinderInfo entry",
+                })
+            }
+        }
+        Ok(Metadata {
+            buf: buf.to_vec(),
+            double,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.buf.clone()
+    }
+
+    /// The parsed entries, for access to the comment, dates and private entries.
+    pub fn apple_double(&self) -> &AppleDouble {
+        &self.double
+    }
+
+    pub fn finder_info(&self, kind: FinderInfoKind) -> FinderInfo {
+        // `from_slice` checked that there are 32 bytes.
+        self.double
+            .finder_info(kind)
+            .ok()
+            .flatten()
+            .expect("FinderInfo entry is present")
+    }
+
+    /// Overwrites the FinderInfo in place, leaving every other byte of the attribute alone.
+    pub fn set_finder_info(&mut self, finder_info: &FinderInfo) {
+        self.patch(EntryId::FINDER_INFO, &finder_info.to_bytes());
+    }
+
+    pub fn file_dates(&self) -> Result<Option<FileDates>> {
+        self.double.file_dates()
+    }
+
+    /// The CNID netatalk last recorded for the file, if any. The CNID database is authoritative;
+    /// this is only a hint.
+    pub fn cnid(&self) -> Option<u32> {
+        match self.double.entry(PRIVATE_ID) {
+            Some(data) if data.len() == 4 => Some(be_u32(data, 0)).filter(|&id| id != 0),
+            _ => None,
+        }
+    }
+
+    /// Overwrites the start of an entry in both the raw bytes and the parsed copy. Only called for
+    /// entries which are known to be present, as the header was validated by `from_slice`.
+    fn patch(&mut self, id: EntryId, data: &[u8]) {
+        let range =
+            entry_range(&self.buf, self.double.entries.len(), id).expect("entry is present");
+        self.buf[range.start..range.start + data.len()].copy_from_slice(data);
+        let entry = self.double.entry_mut(id).expect("entry is present");
+        entry[..data.len()].copy_from_slice(data);
+    }
+}
+
+/// The byte range of the first entry with the given id in an AppleDouble header with `count`
+/// entries, which has already been parsed successfully.
+fn entry_range(buf: &[u8], count: usize, id: EntryId) -> Option<Range<usize>> {
+    (0..count)
+        .map(|i| HEADER_LEN + i * DESCRIPTOR_LEN)
+        .find(|&d| be_u32(buf, d) == id.0)
+        .map(|d| {
+            let offset = be_u32(buf, d + 4) as usize;
+            offset..offset + be_u32(buf, d + 8) as usize
+        })
+}
+
+/// Name of the directory netatalk 2 keeps AppleDouble headers in.
+pub const APPLEDOUBLE_DIR: &str = ".AppleDouble";
+
+/// Name of the header describing the directory itself, inside its `.AppleDouble` directory.
+pub const PARENT_NAME: &str = ".Parent";
+
+/// Reads and writes FinderInfo in netatalk 2's `.AppleDouble` directories, where the header for
+/// `dir/file` is `dir/.AppleDouble/file` and the header for the directory `dir` is
+/// `dir/.AppleDouble/.Parent`.
+///
+/// Existing headers are edited in place, keeping the resource fork and other entries. All-zero
+/// FinderInfo counts as none.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AppleDoubleDirStore;
+
+impl AppleDoubleDirStore {
+    /// The header file holding the metadata of `path`. Whether `path` is a directory decides
+    /// which layout applies, so it must exist.
+    pub fn header_path(path: &Path) -> Result<PathBuf> {
+        match kind_of_path(path)? {
+            FinderInfoKind::Directory => Ok(path.join(APPLEDOUBLE_DIR).join(PARENT_NAME)),
+            FinderInfoKind::File => match path.file_name() {
+                Some(name) => Ok(path.with_file_name(APPLEDOUBLE_DIR).join(name)),
+                None => {
+                    Err(io::Error::new(io::ErrorKind::InvalidInput, "path has no file name").into())
+                }
+            },
+        }
+    }
+
+    /// The paths in `dir` which have a header, including `dir` itself if it has a `.Parent`.
+    /// Headers for files which no longer exist are skipped.
+    pub fn described_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+        let headers = match fs::read_dir(dir.join(APPLEDOUBLE_DIR)) {
+            Ok(headers) => headers,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut paths = Vec::new();
+        for header in headers {
+            let name = header?.file_name();
+            let path = if name == PARENT_NAME {
+                dir.to_path_buf()
+            } else {
+                dir.join(name)
+            };
+            if path.exists() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn read_header(header: &Path) -> Result<Option<Vec<u8>>> {
+        match fs::read(header) {
+            Ok(buf) => Ok(Some(buf)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl FinderInfoStore for AppleDoubleDirStore {
+    fn get(&self, path: &Path) -> Result<Option<FinderInfo>> {
+        let buf = match AppleDoubleDirStore::read_header(&AppleDoubleDirStore::header_path(path)?)?
+        {
+            Some(buf) => buf,
+            None => return Ok(None),
+        };
+        let finder_info = AppleDouble::from_slice(&buf)?.finder_info(kind_of_path(path)?)?;
+        Ok(finder_info.filter(|fi| fi.to_bytes() != [0u8; 32]))
+    }
+
+    fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()> {
+        let header = AppleDoubleDirStore::header_path(path)?;
+        let buf = match AppleDoubleDirStore::read_header(&header)? {
+            Some(buf) => {
+                let mut double = AppleDouble::from_slice(&buf)?;
+                match double.entry(EntryId::FINDER_INFO) {
+                    Some(data) if data.len() >= 32 => {
+                        let mut metadata = Metadata::from_slice(&buf)?;
+                        metadata.set_finder_info(finder_info);
+                        metadata.to_bytes()
+                    }
+                    // There's nowhere to put the FinderInfo without moving other entries.
+                    _ => {
+                        double.set_finder_info(finder_info);
+                        double.to_bytes()?
+                    }
+                }
+            }
+            None => {
+                if let Some(dir) = header.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let mut double = AppleDouble {
+                    filler: FILLER,
+                    ..AppleDouble::new()
+                };
+                double.set_finder_info(finder_info);
+                double.to_bytes()?
+            }
+        };
+        Ok(fs::write(&header, buf)?)
+    }
+
+    /// Zeroes the FinderInfo in place, deleting the header if it held nothing but 32 bytes of
+    /// FinderInfo. Anything after the FinderInfo in its entry, such as extended attributes, is
+    /// kept.
+    fn remove(&self, path: &Path) -> Result<bool> {
+        let header = AppleDoubleDirStore::header_path(path)?;
+        let mut buf = match AppleDoubleDirStore::read_header(&header)? {
+            Some(buf) => buf,
+            None => return Ok(false),
+        };
+        let double = AppleDouble::from_slice(&buf)?;
+        let data = match double.entry(EntryId::FINDER_INFO) {
+            Some(data) => data,
+            None => return Ok(false),
+        };
+        let existed = data.iter().take(32).any(|&b| b != 0);
+        if double.entries.len() == 1 && data.len() == 32 {
+            fs::remove_file(&header)?;
+        } else if existed {
+            let range = entry_range(&buf, double.entries.len(), EntryId::FINDER_INFO)
+                .expect("FinderInfo entry is present");
+            let end = range.start + range.len().min(32);
+            buf[range.start..end].fill(0);
+            fs::write(&header, buf)?;
+        }
+        Ok(existed)
+    }
+}
+
+#[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+pub use self::store::NetatalkStore;
+
+#[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+mod store {
+    use std::path::Path;
+
+    use super::{Metadata, ATTRIBUTE_NAME};
+    use crate::store::FinderInfoStore;
+    use crate::xattr::{self, SetMode};
+    use crate::{kind_of_path, FinderInfo, FinderInfoFile, Result};
+
+    /// Reads and writes FinderInfo in netatalk's metadata attribute.
+    ///
+    /// Netatalk creates the attribute with zero FinderInfo for everything it touches, so all-zero
+    /// FinderInfo counts as none, and removing FinderInfo zeroes it rather than deleting the
+    /// attribute.
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub struct NetatalkStore {
+        name: String,
+    }
+
+    impl Default for NetatalkStore {
+        fn default() -> NetatalkStore {
+            NetatalkStore::new()
+        }
+    }
+
+    impl NetatalkStore {
+        pub fn new() -> NetatalkStore {
+            NetatalkStore::with_name(ATTRIBUTE_NAME)
+        }
+
+        /// Uses the attribute `name`, e.g. `org.netatalk.Metadata` on systems without the `user.`
+        /// prefix.
+        pub fn with_name(name: impl Into<String>) -> NetatalkStore {
+            NetatalkStore { name: name.into() }
+        }
+
+        /// Reads the whole attribute, or `None` if `path` doesn't have one.
+        pub fn metadata(&self, path: &Path) -> Result<Option<Metadata>> {
+            match xattr::get(path, &self.name)? {
+                Some(buf) => Metadata::from_slice(&buf).map(Some),
+                None => Ok(None),
+            }
+        }
+
+        pub fn set_metadata(&self, path: &Path, metadata: &Metadata) -> Result<()> {
+            xattr::set(
+                path,
+                &self.name,
+                &metadata.to_bytes(),
+                SetMode::CreateOrReplace,
+            )
+        }
+    }
+
+    impl FinderInfoStore for NetatalkStore {
+        fn get(&self, path: &Path) -> Result<Option<FinderInfo>> {
+            let metadata = match self.metadata(path)? {
+                Some(metadata) => metadata,
+                None => return Ok(None),
+            };
+            let finder_info = metadata.finder_info(kind_of_path(path)?);
+            Ok(Some(finder_info).filter(|fi| fi.to_bytes() != [0u8; 32]))
+        }
+
+        fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()> {
+            let mut metadata = self.metadata(path)?.unwrap_or_default();
+            metadata.set_finder_info(finder_info);
+            self.set_metadata(path, &metadata)
+        }
+
+        fn remove(&self, path: &Path) -> Result<bool> {
+            let mut metadata = match self.metadata(path)? {
+                Some(metadata) => metadata,
+                None => return Ok(false),
+            };
+            let zero = FinderInfo::File(FinderInfoFile::default());
+            if metadata.finder_info(kind_of_path(path)?).to_bytes() == zero.to_bytes() {
+                return Ok(false);
+            }
+            metadata.set_finder_info(&zero);
+            self.set_metadata(path, &metadata)?;
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::appledouble::ExtendedAttribute;
+    use crate::test_util::ScratchDir;
+    use crate::{FinderInfoFile, FinderInfoFolder, OSType};
+
+    #[test]
+    fn test_netatalk_metadata() {
+        let metadata = Metadata::new();
+        let buf = metadata.to_bytes();
+        assert_eq!(buf.len(), 402);
+        assert_eq!(&buf[8..24], b"Netatalk        ");
+        // FinderInfo at 122, comment at 154, dates at 354.
+        assert_eq!(&buf[26..38], &[0, 0, 0, 9, 0, 0, 0, 122, 0, 0, 0, 32]);
+        assert_eq!(&buf[38..50], &[0, 0, 0, 4, 0, 0, 0, 154, 0, 0, 0, 0]);
+        assert_eq!(&buf[50..54], &[0, 0, 0, 8]);
+        assert_eq!(&buf[54..58], &[0, 0, 1, 98]);
+        assert_eq!(&buf[354..358], &[0x80, 0, 0, 0]);
+        assert_eq!(metadata.cnid(), None);
+
+        // Pretend netatalk assigned a CNID, then change the FinderInfo.
+        let mut buf = buf;
+        buf[398..402].copy_from_slice(&[0, 0, 0, 42]);
+        let mut metadata = Metadata::from_slice(&buf).unwrap();
+        assert_eq!(metadata.cnid(), Some(42));
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(b"TEXT");
+        metadata.set_finder_info(&FinderInfo::File(file));
+
+        let rewritten = metadata.to_bytes();
+        assert_eq!(&rewritten[122..126], b"TEXT");
+        assert_eq!(&rewritten[..122], &buf[..122]);
+        assert_eq!(&rewritten[154..], &buf[154..]);
+        let metadata = Metadata::from_slice(&rewritten).unwrap();
+        assert_eq!(
+            metadata.finder_info(FinderInfoKind::File),
+            FinderInfo::File(file)
+        );
+        assert_eq!(
+            metadata.finder_info(FinderInfoKind::Directory).kind(),
+            FinderInfo::Directory(FinderInfoFolder::default()).kind()
+        );
+        assert_eq!(metadata.file_dates().unwrap(), Some(FileDates::default()));
+
+        match Metadata::from_slice(&AppleDouble::new().to_bytes().unwrap()) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+    #[test]
+    fn test_netatalk_store() {
+        let dir = match ScratchDir::with_xattrs("netatalk") {
+            Some(dir) => dir,
+            None => return,
+        };
+        let store = NetatalkStore::new();
+        let folder = FinderInfo::Directory(FinderInfoFolder::default());
+        store.set(&dir, &folder).unwrap();
+        assert_eq!(store.get(&dir).unwrap(), None);
+
+        let mut metadata = store.metadata(&dir).unwrap().unwrap();
+        let mut buf = metadata.to_bytes();
+        buf[398..402].copy_from_slice(&[0, 0, 0, 7]);
+        metadata = Metadata::from_slice(&buf).unwrap();
+        store.set_metadata(&dir, &metadata).unwrap();
+
+        let mut folder = FinderInfoFolder::default();
+        folder
+            .folder_info
+            .finderFlags
+            .set_color(Some(crate::LabelColor::Red));
+        store.set(&dir, &FinderInfo::Directory(folder)).unwrap();
+        assert_eq!(
+            store.get(&dir).unwrap(),
+            Some(FinderInfo::Directory(folder))
+        );
+        assert_eq!(store.metadata(&dir).unwrap().unwrap().cnid(), Some(7));
+        assert!(store.remove(&dir).unwrap());
+        assert!(!store.remove(&dir).unwrap());
+        assert_eq!(store.metadata(&dir).unwrap().unwrap().cnid(), Some(7));
+    }
+
+    #[test]
+    fn test_appledouble_dir_store() {
+        use crate::store::{migrate, MemoryStore};
+
+        let dir = ScratchDir::new("adouble");
+        let sub = dir.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        let file = dir.join("file");
+        fs::write(&file, b"").unwrap();
+        let store = AppleDoubleDirStore;
+        assert_eq!(
+            AppleDoubleDirStore::header_path(&file).unwrap(),
+            dir.join(".AppleDouble/file")
+        );
+        assert_eq!(
+            AppleDoubleDirStore::header_path(&sub).unwrap(),
+            sub.join(".AppleDouble/.Parent")
+        );
+
+        // A header as netatalk 2 writes it, with the resource fork after the FinderInfo.
+        let mut double = AppleDouble {
+            filler: FILLER,
+            ..AppleDouble::new()
+        };
+        double.set_real_name("file").unwrap();
+        double.set_finder_info(&FinderInfo::Unknown([0u8; 32]));
+        double.set_resource_fork(vec![0xaa; 300]);
+        let original = double.to_bytes().unwrap();
+        fs::create_dir(dir.join(".AppleDouble")).unwrap();
+        fs::write(dir.join(".AppleDouble/file"), &original).unwrap();
+        assert_eq!(store.get(&file).unwrap(), None);
+
+        let mut info = FinderInfoFile::default();
+        info.file_info.fileType = OSType::new(b"TEXT");
+        store.set(&file, &FinderInfo::File(info)).unwrap();
+        let rewritten = fs::read(dir.join(".AppleDouble/file")).unwrap();
+        assert_eq!(rewritten.len(), original.len());
+        let double = AppleDouble::from_slice(&rewritten).unwrap();
+        assert_eq!(double.resource_fork(), Some(&[0xaa; 300][..]));
+        assert_eq!(store.get(&file).unwrap(), Some(FinderInfo::File(info)));
+
+        let mut flagged = FinderInfoFolder::default();
+        flagged.folder_info.finderFlags.set_is_on_desk(true);
+        store.set(&sub, &FinderInfo::Directory(flagged)).unwrap();
+        assert!(sub.join(".AppleDouble/.Parent").exists());
+        assert_eq!(
+            store.get(&sub).unwrap(),
+            Some(FinderInfo::Directory(flagged))
+        );
+        assert_eq!(
+            AppleDoubleDirStore::described_paths(&dir).unwrap(),
+            vec![file.clone()]
+        );
+        assert_eq!(
+            AppleDoubleDirStore::described_paths(&sub).unwrap(),
+            vec![sub.clone()]
+        );
+
+        let memory = MemoryStore::new();
+        for path in &[&file, &sub] {
+            assert!(migrate(&store, &memory, path).unwrap());
+            assert!(!store.exists(path).unwrap());
+        }
+        assert_eq!(memory.get(&file).unwrap(), Some(FinderInfo::File(info)));
+        assert_eq!(memory.len(), 2);
+        // The resource fork keeps the file's header alive; the folder's only had FinderInfo.
+        assert!(dir.join(".AppleDouble/file").exists());
+        assert!(!sub.join(".AppleDouble/.Parent").exists());
+        assert!(!migrate(&store, &memory, &file).unwrap());
+
+        // Extended attributes after the FinderInfo keep the header alive even on its own.
+        let mut double = AppleDouble::new();
+        double.set_finder_info(&FinderInfo::File(info));
+        double
+            .set_extended_attributes(&[ExtendedAttribute::new("user.test", &b"value"[..])])
+            .unwrap();
+        fs::write(sub.join(".AppleDouble/.Parent"), double.to_bytes().unwrap()).unwrap();
+        assert!(store.remove(&sub).unwrap());
+        let double =
+            AppleDouble::from_slice(&fs::read(sub.join(".AppleDouble/.Parent")).unwrap()).unwrap();
+        assert_eq!(double.extended_attributes().unwrap().len(), 1);
+        assert_eq!(store.get(&sub).unwrap(), None);
+
+        // A FinderInfo entry too short to hold FinderInfo is zeroed without being resized.
+        let mut double = AppleDouble::new();
+        double.set_entry(EntryId::FINDER_INFO, vec![0xff; 16]);
+        double.set_resource_fork(vec![0xaa; 10]);
+        let original = double.to_bytes().unwrap();
+        fs::write(dir.join(".AppleDouble/file"), &original).unwrap();
+        assert!(store.remove(&file).unwrap());
+        let double =
+            AppleDouble::from_slice(&fs::read(dir.join(".AppleDouble/file")).unwrap()).unwrap();
+        assert_eq!(double.entry(EntryId::FINDER_INFO), Some(&[0u8; 16][..]));
+        assert_eq!(double.resource_fork(), Some(&[0xaa; 10][..]));
+    }
+}
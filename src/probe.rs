@@ -0,0 +1,222 @@
+//! Finding out where the FinderInfo of a path on a Linux server is kept.
+//!
+//! Shares which have been served by different generations of netatalk and Samba, or copied around
+//! with rsync, can carry FinderInfo in several places at once, not always in agreement. `probe`
+//! looks in all of them.
+
+use std::fs;
+use std::path::Path;
+
+use crate::afpinfo::SambaStore;
+use crate::netatalk::{AppleDoubleDirStore, NetatalkStore};
+use crate::store::{AppleDoubleStore, FinderInfoStore};
+use crate::xattr::{self, XattrBackend};
+use crate::{Error, FinderInfo, Result};
+
+/// A place FinderInfo can be kept.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum Representation {
+    /// The `user.com.apple.FinderInfo` extended attribute.
+    Xattr,
+    /// Samba's `AFP_AfpInfo` stream attribute.
+    Samba,
+    /// The netatalk 3 `user.org.netatalk.Metadata` attribute.
+    Netatalk,
+    /// An AppleDouble `._` sidecar file.
+    AppleDoubleSidecar,
+    /// A netatalk 2 `.AppleDouble` directory entry.
+    AppleDoubleDir,
+}
+
+impl Representation {
+    /// The default priority: the plain attribute first, then what SMB and AFP clients see, then
+    /// the AppleDouble files older tools left behind.
+    pub const DEFAULT_PRIORITY: [Representation; 5] = [
+        Representation::Xattr,
+        Representation::Samba,
+        Representation::Netatalk,
+        Representation::AppleDoubleSidecar,
+        Representation::AppleDoubleDir,
+    ];
+
+    /// A store reading and writing this representation with the default attribute names.
+    pub fn store(self) -> Box<dyn FinderInfoStore> {
+        match self {
+            Representation::Xattr => Box::new(XattrBackend::new()),
+            Representation::Samba => Box::new(SambaStore::new()),
+            Representation::Netatalk => Box::new(NetatalkStore::new()),
+            Representation::AppleDoubleSidecar => Box::new(AppleDoubleStore),
+            Representation::AppleDoubleDir => Box::new(AppleDoubleDirStore),
+        }
+    }
+}
+
+/// Everything `probe` found for a path.
+#[derive(Debug)]
+pub struct Probe {
+    /// The FinderInfo in each representation which has any, in priority order.
+    pub found: Vec<(Representation, FinderInfo)>,
+    /// The representations which are present but couldn't be read, e.g. a truncated attribute.
+    pub errors: Vec<(Representation, Error)>,
+}
+
+impl Probe {
+    /// The FinderInfo from the representation with the highest priority.
+    pub fn authoritative(&self) -> Option<(Representation, FinderInfo)> {
+        self.found.first().copied()
+    }
+
+    /// The representations whose FinderInfo differs from the authoritative one.
+    pub fn disagreements(&self) -> Vec<Representation> {
+        let authoritative = match self.found.first() {
+            Some((_, finder_info)) => finder_info.to_bytes(),
+            None => return Vec::new(),
+        };
+        self.found
+            .iter()
+            .filter(|(_, finder_info)| finder_info.to_bytes() != authoritative)
+            .map(|&(representation, _)| representation)
+            .collect()
+    }
+
+    /// Whether every representation which was found agrees and none were unreadable.
+    pub fn is_consistent(&self) -> bool {
+        self.errors.is_empty() && self.disagreements().is_empty()
+    }
+}
+
+/// Probes paths with a configurable priority.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Prober {
+    priority: Vec<Representation>,
+}
+
+impl Default for Prober {
+    fn default() -> Prober {
+        Prober::new()
+    }
+}
+
+impl Prober {
+    /// Uses `Representation::DEFAULT_PRIORITY`.
+    pub fn new() -> Prober {
+        Prober::with_priority(Representation::DEFAULT_PRIORITY.to_vec())
+    }
+
+    /// Looks in the given representations only, highest priority first.
+    pub fn with_priority(priority: Vec<Representation>) -> Prober {
+        Prober { priority }
+    }
+
+    pub fn priority(&self) -> &[Representation] {
+        &self.priority
+    }
+
+    /// Reads the FinderInfo of `path` from every representation. All-zero FinderInfo counts as
+    /// none, as some tools write it as a placeholder. On filesystems without extended attributes,
+    /// such as many NFS and FUSE mounts, the representations kept in them count as absent.
+    ///
+    /// Only fails if `path` itself can't be looked at; problems with a representation are
+    /// reported in `Probe::errors`.
+    pub fn probe(&self, path: &Path) -> Result<Probe> {
+        fs::metadata(path)?;
+        let mut probe = Probe {
+            found: Vec::new(),
+            errors: Vec::new(),
+        };
+        for &representation in &self.priority {
+            match representation.store().get(path) {
+                Ok(Some(finder_info)) if finder_info.to_bytes() != [0u8; 32] => {
+                    probe.found.push((representation, finder_info))
+                }
+                Ok(_) => {}
+                Err(ref e) if xattr::is_unsupported(e) => {}
+                Err(e) => probe.errors.push((representation, e)),
+            }
+        }
+        Ok(probe)
+    }
+}
+
+/// Probes `path` with the default priority.
+pub fn probe(path: &Path) -> Result<Probe> {
+    Prober::new().probe(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netatalk;
+    use crate::test_util::ScratchDir;
+    use crate::xattr::SetMode;
+    use crate::{FinderInfoFile, OSType};
+
+    fn file_of_type(code: &[u8; 4]) -> FinderInfo {
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(code);
+        FinderInfo::File(file)
+    }
+
+    #[test]
+    fn test_probe() {
+        let dir = match ScratchDir::with_xattrs("probe") {
+            Some(dir) => dir,
+            None => return,
+        };
+        let path = dir.join("file");
+        fs::write(&path, b"").unwrap();
+
+        let text = file_of_type(b"TEXT");
+        XattrBackend::new().set(&path, &text).unwrap();
+        SambaStore::new().set(&path, &text).unwrap();
+        AppleDoubleStore.set(&path, &file_of_type(b"PDF ")).unwrap();
+        xattr::set(
+            &path,
+            netatalk::ATTRIBUTE_NAME,
+            b"garbage",
+            SetMode::CreateOrReplace,
+        )
+        .unwrap();
+
+        let found = probe(&path).unwrap();
+        let representations: Vec<_> = found.found.iter().map(|&(r, _)| r).collect();
+        assert_eq!(
+            representations,
+            vec![
+                Representation::Xattr,
+                Representation::Samba,
+                Representation::AppleDoubleSidecar
+            ]
+        );
+        assert_eq!(found.authoritative(), Some((Representation::Xattr, text)));
+        assert_eq!(
+            found.disagreements(),
+            vec![Representation::AppleDoubleSidecar]
+        );
+        assert_eq!(found.errors.len(), 1);
+        assert_eq!(found.errors[0].0, Representation::Netatalk);
+        assert!(!found.is_consistent());
+
+        let prober = Prober::with_priority(vec![
+            Representation::AppleDoubleSidecar,
+            Representation::Xattr,
+        ]);
+        let found = prober.probe(&path).unwrap();
+        assert_eq!(
+            found.authoritative(),
+            Some((Representation::AppleDoubleSidecar, file_of_type(b"PDF ")))
+        );
+        assert_eq!(found.disagreements(), vec![Representation::Xattr]);
+
+        let found = Prober::with_priority(vec![Representation::Xattr, Representation::Samba])
+            .probe(&path)
+            .unwrap();
+        assert!(found.is_consistent());
+
+        match probe(&dir.join("missing")) {
+            Err(Error::Io(ref e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,413 @@
+//! A table of well-known classic Mac OS file type and creator codes.
+//!
+//! Classic Mac OS identified file formats by their four character type code rather than by
+//! extension, so files which come from it often have no extension at all. This module maps the
+//! common type codes to their modern equivalents (UTI, MIME type and extension) and back, and maps
+//! common creator codes to the applications they belong to.
+//!
+//! The table is necessarily incomplete; it covers the codes which are most likely to turn up on old
+//! volumes and in archives.
+
+use crate::OSType;
+
+/// A well-known file type code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TypeCode {
+    pub code: OSType,
+    /// The Uniform Type Identifier macOS uses for the type, e.g. `public.plain-text`.
+    pub uti: &'static str,
+    pub mime_type: &'static str,
+    /// The extensions used for the type, without a leading dot, preferred first. Empty if files of
+    /// the type don't have one.
+    pub extensions: &'static [&'static str],
+    pub description: &'static str,
+}
+
+/// A well-known creator code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CreatorCode {
+    pub code: OSType,
+    /// The name of the application which uses the code.
+    pub application: &'static str,
+}
+
+const fn file_type(
+    code: &[u8; 4],
+    uti: &'static str,
+    mime_type: &'static str,
+    extensions: &'static [&'static str],
+    description: &'static str,
+) -> TypeCode {
+    TypeCode {
+        code: OSType::new(code),
+        uti,
+        mime_type,
+        extensions,
+        description,
+    }
+}
+
+impl TypeCode {
+    /// The preferred extension, or `None` if files of the type don't have one.
+    pub fn extension(&self) -> Option<&'static str> {
+        self.extensions.first().copied()
+    }
+}
+
+const fn creator(code: &[u8; 4], application: &'static str) -> CreatorCode {
+    CreatorCode {
+        code: OSType::new(code),
+        application,
+    }
+}
+
+/// Known file types. Where several codes share a UTI, MIME type or extension, the preferred code
+/// comes first, since that is the one the reverse lookups return.
+pub const FILE_TYPES: &[TypeCode] = &[
+    file_type(
+        b"TEXT",
+        "public.plain-text",
+        "text/plain",
+        &["txt"],
+        "Plain text",
+    ),
+    file_type(
+        b"utxt",
+        "public.utf16-plain-text",
+        "text/plain",
+        &["txt"],
+        "Unicode text",
+    ),
+    file_type(
+        b"ttro",
+        "public.plain-text",
+        "text/plain",
+        &["txt"],
+        "Read-only text",
+    ),
+    file_type(b"RTF ", "public.rtf", "text/rtf", &["rtf"], "Rich text"),
+    file_type(
+        b"HTML",
+        "public.html",
+        "text/html",
+        &["html", "htm"],
+        "HTML document",
+    ),
+    file_type(
+        b"PDF ",
+        "com.adobe.pdf",
+        "application/pdf",
+        &["pdf"],
+        "PDF document",
+    ),
+    file_type(
+        b"PICT",
+        "com.apple.pict",
+        "image/x-pict",
+        &["pict", "pct", "pic"],
+        "PICT image",
+    ),
+    file_type(
+        b"JPEG",
+        "public.jpeg",
+        "image/jpeg",
+        &["jpg", "jpeg", "jpe"],
+        "JPEG image",
+    ),
+    file_type(
+        b"GIFf",
+        "com.compuserve.gif",
+        "image/gif",
+        &["gif"],
+        "GIF image",
+    ),
+    file_type(b"PNGf", "public.png", "image/png", &["png"], "PNG image"),
+    file_type(
+        b"TIFF",
+        "public.tiff",
+        "image/tiff",
+        &["tiff", "tif"],
+        "TIFF image",
+    ),
+    file_type(
+        b"BMPf",
+        "com.microsoft.bmp",
+        "image/bmp",
+        &["bmp"],
+        "BMP image",
+    ),
+    file_type(
+        b"BMP ",
+        "com.microsoft.bmp",
+        "image/bmp",
+        &["bmp"],
+        "BMP image",
+    ),
+    file_type(
+        b"8BPS",
+        "com.adobe.photoshop-image",
+        "image/vnd.adobe.photoshop",
+        &["psd"],
+        "Photoshop document",
+    ),
+    file_type(
+        b"EPSF",
+        "com.adobe.encapsulated-postscript",
+        "application/postscript",
+        &["eps", "epsf"],
+        "Encapsulated PostScript",
+    ),
+    file_type(
+        b"AIFF",
+        "public.aiff-audio",
+        "audio/aiff",
+        &["aiff", "aif"],
+        "AIFF audio",
+    ),
+    file_type(
+        b"AIFC",
+        "public.aifc-audio",
+        "audio/aiff",
+        &["aifc"],
+        "AIFF-C audio",
+    ),
+    file_type(
+        b"WAVE",
+        "com.microsoft.waveform-audio",
+        "audio/wav",
+        &["wav", "wave"],
+        "WAVE audio",
+    ),
+    file_type(b"MPG3", "public.mp3", "audio/mpeg", &["mp3"], "MP3 audio"),
+    file_type(
+        b"M4A ",
+        "com.apple.m4a-audio",
+        "audio/mp4",
+        &["m4a"],
+        "MPEG-4 audio",
+    ),
+    file_type(
+        b"MooV",
+        "com.apple.quicktime-movie",
+        "video/quicktime",
+        &["mov", "qt"],
+        "QuickTime movie",
+    ),
+    file_type(
+        b"MPEG",
+        "public.mpeg",
+        "video/mpeg",
+        &["mpg", "mpeg"],
+        "MPEG movie",
+    ),
+    file_type(
+        b"W8BN",
+        "com.microsoft.word.doc",
+        "application/msword",
+        &["doc"],
+        "Microsoft Word document",
+    ),
+    file_type(
+        b"WDBN",
+        "com.microsoft.word.doc",
+        "application/msword",
+        &["doc"],
+        "Microsoft Word document",
+    ),
+    file_type(
+        b"XLS8",
+        "com.microsoft.excel.xls",
+        "application/vnd.ms-excel",
+        &["xls"],
+        "Microsoft Excel workbook",
+    ),
+    file_type(
+        b"SLD8",
+        "com.microsoft.powerpoint.ppt",
+        "application/vnd.ms-powerpoint",
+        &["ppt"],
+        "Microsoft PowerPoint presentation",
+    ),
+    file_type(
+        b"ZIP ",
+        "public.zip-archive",
+        "application/zip",
+        &["zip"],
+        "Zip archive",
+    ),
+    file_type(
+        b"SITD",
+        "com.stuffit.archive.sit",
+        "application/x-stuffit",
+        &["sit"],
+        "StuffIt archive",
+    ),
+    file_type(
+        b"SIT!",
+        "com.stuffit.archive.sit",
+        "application/x-stuffit",
+        &["sit"],
+        "StuffIt archive",
+    ),
+    file_type(
+        b"sfnt",
+        "public.truetype-ttf-font",
+        "font/ttf",
+        &["ttf"],
+        "TrueType font",
+    ),
+    file_type(
+        b"FFIL",
+        "com.apple.font-suitcase",
+        "application/octet-stream",
+        &["suit"],
+        "Font suitcase",
+    ),
+    file_type(
+        b"APPL",
+        "com.apple.application-file",
+        "application/octet-stream",
+        &[],
+        "Application",
+    ),
+    file_type(
+        b"BINA",
+        "public.data",
+        "application/octet-stream",
+        &["bin"],
+        "Binary data",
+    ),
+];
+
+/// Known creator codes.
+pub const CREATORS: &[CreatorCode] = &[
+    creator(b"ttxt", "SimpleText"),
+    creator(b"MACS", "Finder"),
+    creator(b"MSWD", "Microsoft Word"),
+    creator(b"XCEL", "Microsoft Excel"),
+    creator(b"PPT3", "Microsoft PowerPoint"),
+    creator(b"8BIM", "Adobe Photoshop"),
+    creator(b"ARTS", "Adobe Illustrator"),
+    creator(b"CARO", "Adobe Acrobat"),
+    creator(b"ALD6", "Adobe PageMaker"),
+    creator(b"XPR3", "QuarkXPress"),
+    creator(b"R*ch", "BBEdit"),
+    creator(b"BOBO", "ClarisWorks"),
+    creator(b"FMP3", "FileMaker Pro"),
+    creator(b"SIT!", "StuffIt"),
+    creator(b"prvw", "Preview"),
+    creator(b"TVOD", "QuickTime Player"),
+    creator(b"ogle", "PictureViewer"),
+    creator(b"GKON", "GraphicConverter"),
+    creator(b"hook", "iTunes"),
+    creator(b"MOSS", "Netscape Navigator"),
+    creator(b"MSIE", "Internet Explorer"),
+    creator(b"sfri", "Safari"),
+    creator(b"ToyS", "Script Editor"),
+    creator(b"CWIE", "CodeWarrior"),
+    creator(b"MPS ", "MPW Shell"),
+];
+
+/// Looks up a file type code.
+pub fn lookup_file_type(code: OSType) -> Option<&'static TypeCode> {
+    FILE_TYPES.iter().find(|t| t.code == code)
+}
+
+/// Looks up a creator code.
+pub fn lookup_creator(code: OSType) -> Option<&'static CreatorCode> {
+    CREATORS.iter().find(|c| c.code == code)
+}
+
+/// Finds the preferred file type for an extension, which may be any of a type's extensions. The
+/// comparison ignores case and a leading dot.
+pub fn file_type_for_extension(extension: &str) -> Option<&'static TypeCode> {
+    let extension = extension.strip_prefix('.').unwrap_or(extension);
+    FILE_TYPES.iter().find(|t| {
+        t.extensions
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(extension))
+    })
+}
+
+/// Finds the preferred file type for a MIME type. Parameters such as `; charset=utf-8` are ignored.
+pub fn file_type_for_mime_type(mime_type: &str) -> Option<&'static TypeCode> {
+    let essence = mime_type.split(';').next().unwrap_or("").trim();
+    FILE_TYPES
+        .iter()
+        .find(|t| t.mime_type.eq_ignore_ascii_case(essence))
+}
+
+/// Finds the preferred file type for a Uniform Type Identifier.
+pub fn file_type_for_uti(uti: &str) -> Option<&'static TypeCode> {
+    FILE_TYPES.iter().find(|t| t.uti.eq_ignore_ascii_case(uti))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookups() {
+        let text = lookup_file_type(OSType::new(b"TEXT")).unwrap();
+        assert_eq!(text.uti, "public.plain-text");
+        assert_eq!(text.extension(), Some("txt"));
+        assert_eq!(
+            lookup_file_type(OSType::new(b"APPL")).unwrap().extension(),
+            None
+        );
+        assert_eq!(
+            lookup_file_type(OSType::new(b"PDF ")).unwrap().mime_type,
+            "application/pdf"
+        );
+        assert_eq!(lookup_file_type(OSType::new(b"zzzz")), None);
+        assert_eq!(
+            lookup_creator(OSType::new(b"8BIM")).unwrap().application,
+            "Adobe Photoshop"
+        );
+
+        assert_eq!(file_type_for_extension(".TXT"), Some(text));
+        assert_eq!(
+            file_type_for_mime_type("text/plain; charset=utf-8"),
+            Some(text)
+        );
+        assert_eq!(
+            file_type_for_uti("com.compuserve.gif").unwrap().code,
+            OSType::new(b"GIFf")
+        );
+        assert_eq!(
+            file_type_for_extension("bmp").unwrap().code,
+            OSType::new(b"BMPf")
+        );
+        for &(extension, code) in &[("jpeg", b"JPEG"), ("TIF", b"TIFF"), ("htm", b"HTML")] {
+            assert_eq!(
+                file_type_for_extension(extension).unwrap().code,
+                OSType::new(code)
+            );
+        }
+        assert_eq!(file_type_for_extension("app"), None);
+    }
+
+    #[test]
+    fn test_table_is_consistent() {
+        for (i, t) in FILE_TYPES.iter().enumerate() {
+            assert!(t.code.is_printable(), "{:?}", t.code);
+            assert!(
+                t.extensions.iter().all(|e| !e.starts_with('.')),
+                "{:?}",
+                t.code
+            );
+            assert!(
+                FILE_TYPES[..i].iter().all(|other| other.code != t.code),
+                "duplicate {:?}",
+                t.code
+            );
+        }
+        for (i, c) in CREATORS.iter().enumerate() {
+            assert!(
+                CREATORS[..i].iter().all(|other| other.code != c.code),
+                "duplicate {:?}",
+                c.code
+            );
+        }
+    }
+}
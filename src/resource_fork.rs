@@ -0,0 +1,362 @@
+//! Classic Mac OS resource forks.
+//!
+//! A resource fork starts with a 16 byte header giving the position and length of the resource
+//! data and of the resource map. Each resource's data is stored as a 4 byte length followed by the
+//! bytes. The map holds a list of types, each pointing at a list of references (ID, name,
+//! attributes and data offset), and a list of names as Pascal strings.
+//!
+//! The 240 bytes after the header, reserved for the system and the application, are not kept;
+//! they are written as zeros.
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use crate::{be_u16, be_u32, macroman, Error, OSType, Result};
+
+// Resource attributes
+/// The resource is read into the system heap.
+pub const resSysHeap: u8 = 0x40;
+/// The resource may be purged from memory.
+pub const resPurgeable: u8 = 0x20;
+/// The resource is locked in memory.
+pub const resLocked: u8 = 0x10;
+/// The resource can't be changed.
+pub const resProtected: u8 = 0x08;
+/// The resource is read in when the fork is opened.
+pub const resPreload: u8 = 0x04;
+/// The resource has been changed in memory and needs writing.
+pub const resChanged: u8 = 0x02;
+
+// Resource map attributes
+/// The fork can't be written to.
+pub const mapReadOnly: u16 = 0x0080;
+/// The fork will be compacted when it is closed.
+pub const mapCompact: u16 = 0x0040;
+/// The map has been changed and needs writing.
+pub const mapChanged: u16 = 0x0020;
+
+const HEADER_LEN: usize = 16;
+/// The header plus the reserved system and application areas, where the data starts.
+const DATA_START: usize = 256;
+/// The map starts with a copy of the header, a handle, a file reference number, the attributes and
+/// the offsets of the type and name lists.
+const MAP_HEADER_LEN: usize = 28;
+const TYPE_LEN: usize = 8;
+const REFERENCE_LEN: usize = 12;
+const NO_NAME: u16 = 0xffff;
+
+/// Returns `buf[offset..offset + len]`, or an error if it runs past the end of `buf`.
+fn slice(buf: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| buf.get(offset..end))
+        .ok_or(Error::Truncated { offset: buf.len() })
+}
+
+fn too_large(field: &'static str, max: usize, actual: usize) -> Error {
+    Error::TooLarge { field, max, actual }
+}
+
+/// A single resource.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Resource {
+    pub resource_type: OSType,
+    pub id: i16,
+    /// The name, decoded from Mac OS Roman. Most resources don't have one.
+    pub name: Option<String>,
+    /// Attributes such as `resPurgeable`.
+    pub attributes: u8,
+    pub data: Vec<u8>,
+}
+
+impl Resource {
+    pub fn new(resource_type: OSType, id: i16, data: Vec<u8>) -> Resource {
+        Resource {
+            resource_type,
+            id,
+            name: None,
+            attributes: 0,
+            data,
+        }
+    }
+}
+
+/// The resources of a resource fork, in the order they are stored.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ResourceFork {
+    /// Attributes of the whole map, such as `mapReadOnly`.
+    pub attributes: u16,
+    pub resources: Vec<Resource>,
+}
+
+impl ResourceFork {
+    /// Reads a resource fork to the end of `r`.
+    pub fn read<R: Read>(r: &mut R) -> Result<ResourceFork> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        ResourceFork::from_slice(&buf)
+    }
+
+    /// Parses a resource fork. An empty fork has no resources.
+    pub fn from_slice(buf: &[u8]) -> Result<ResourceFork> {
+        if buf.is_empty() {
+            return Ok(ResourceFork::default());
+        }
+        let header = slice(buf, 0, HEADER_LEN)?;
+        let data_offset = be_u32(header, 0) as usize;
+        let map_offset = be_u32(header, 4) as usize;
+        let data_len = be_u32(header, 8) as usize;
+        let map_len = be_u32(header, 12) as usize;
+        let data = slice(buf, data_offset, data_len)?;
+        let map = slice(buf, map_offset, map_len)?;
+        slice(map, 0, MAP_HEADER_LEN)?;
+
+        let attributes = be_u16(map, 22);
+        let type_list = usize::from(be_u16(map, 24));
+        let name_list = usize::from(be_u16(map, 26));
+        let type_count = usize::from(be_u16(slice(map, type_list, 2)?, 0).wrapping_add(1));
+
+        let mut resources = Vec::new();
+        for i in 0..type_count {
+            let entry = slice(map, type_list + 2 + i * TYPE_LEN, TYPE_LEN)?;
+            let resource_type = OSType([entry[0], entry[1], entry[2], entry[3]]);
+            let count = usize::from(be_u16(entry, 4)) + 1;
+            let references = type_list + usize::from(be_u16(entry, 6));
+            for j in 0..count {
+                let reference = slice(map, references + j * REFERENCE_LEN, REFERENCE_LEN)?;
+                let id = be_u16(reference, 0) as i16;
+                let name_offset = be_u16(reference, 2);
+                let attributes = reference[4];
+                let offset = (be_u32(reference, 4) & 0x00ff_ffff) as usize;
+
+                let name = if name_offset == NO_NAME {
+                    None
+                } else {
+                    let start = name_list + usize::from(name_offset);
+                    let len = usize::from(slice(map, start, 1)?[0]);
+                    Some(macroman::decode(slice(map, start + 1, len)?))
+                };
+                let len = be_u32(slice(data, offset, 4)?, 0) as usize;
+                resources.push(Resource {
+                    resource_type,
+                    id,
+                    name,
+                    attributes,
+                    data: slice(data, offset + 4, len)?.to_vec(),
+                });
+            }
+        }
+        Ok(ResourceFork {
+            attributes,
+            resources,
+        })
+    }
+
+    /// Serializes the fork. Resources of the same type are grouped together in the map, in the
+    /// order their types first appear.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut types: Vec<(OSType, Vec<&Resource>)> = Vec::new();
+        for resource in &self.resources {
+            match types.iter_mut().find(|(t, _)| *t == resource.resource_type) {
+                Some((_, list)) => list.push(resource),
+                None => types.push((resource.resource_type, vec![resource])),
+            }
+        }
+
+        let mut data = Vec::new();
+        let mut names = Vec::new();
+        let type_list_len = 2 + types.len() * TYPE_LEN;
+        let mut type_list = Vec::with_capacity(type_list_len);
+        let mut references = Vec::with_capacity(self.resources.len() * REFERENCE_LEN);
+        type_list.extend_from_slice(&(types.len() as u16).wrapping_sub(1).to_be_bytes());
+        for (resource_type, list) in &types {
+            // The count is stored less one.
+            let count = u16::try_from(list.len() - 1)
+                .map_err(|_| too_large("resource count", 0x10000, list.len()))?;
+            let offset = u16::try_from(type_list_len + references.len()).map_err(|_| {
+                too_large(
+                    "reference list offset",
+                    0xffff,
+                    type_list_len + references.len(),
+                )
+            })?;
+            type_list.extend_from_slice(&resource_type.0);
+            type_list.extend_from_slice(&count.to_be_bytes());
+            type_list.extend_from_slice(&offset.to_be_bytes());
+            for resource in list {
+                let name_offset = match &resource.name {
+                    Some(name) => {
+                        let encoded = macroman::encode(name)
+                            .filter(|n| n.len() <= 255)
+                            .ok_or_else(|| Error::Parse {
+                                input: name.clone(),
+                                expected: "a Mac OS Roman resource name of at most 255 bytes",
+                            })?;
+                        let offset = u16::try_from(names.len())
+                            .ok()
+                            .filter(|&o| o != NO_NAME)
+                            .ok_or_else(|| too_large("name offset", 0xfffe, names.len()))?;
+                        names.push(encoded.len() as u8);
+                        names.extend_from_slice(&encoded);
+                        offset
+                    }
+                    None => NO_NAME,
+                };
+                if data.len() > 0x00ff_ffff {
+                    return Err(too_large("data offset", 0x00ff_ffff, data.len()));
+                }
+                let len = u32::try_from(resource.data.len()).map_err(|_| {
+                    too_large("resource length", u32::MAX as usize, resource.data.len())
+                })?;
+                references.extend_from_slice(&resource.id.to_be_bytes());
+                references.extend_from_slice(&name_offset.to_be_bytes());
+                references.push(resource.attributes);
+                references.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+                references.extend_from_slice(&[0u8; 4]);
+                data.extend_from_slice(&len.to_be_bytes());
+                data.extend_from_slice(&resource.data);
+            }
+        }
+
+        let name_list = MAP_HEADER_LEN + type_list.len() + references.len();
+        let name_list = u16::try_from(name_list)
+            .map_err(|_| too_large("name list offset", 0xffff, name_list))?;
+        let map_len = usize::from(name_list) + names.len();
+        let map_offset = DATA_START + data.len();
+        let mut header = [0u8; HEADER_LEN];
+        let to_u32 = |field: &'static str, n: usize| {
+            u32::try_from(n).map_err(|_| too_large(field, u32::MAX as usize, n))
+        };
+        header[0..4].copy_from_slice(&(DATA_START as u32).to_be_bytes());
+        header[4..8].copy_from_slice(&to_u32("map offset", map_offset)?.to_be_bytes());
+        header[8..12].copy_from_slice(&to_u32("data length", data.len())?.to_be_bytes());
+        header[12..16].copy_from_slice(&to_u32("map length", map_len)?.to_be_bytes());
+
+        let mut buf = Vec::with_capacity(map_offset + map_len);
+        buf.extend_from_slice(&header);
+        buf.resize(DATA_START, 0);
+        buf.extend_from_slice(&data);
+        buf.extend_from_slice(&header);
+        buf.extend_from_slice(&[0u8; 6]);
+        buf.extend_from_slice(&self.attributes.to_be_bytes());
+        buf.extend_from_slice(&(MAP_HEADER_LEN as u16).to_be_bytes());
+        buf.extend_from_slice(&name_list.to_be_bytes());
+        buf.extend_from_slice(&type_list);
+        buf.extend_from_slice(&references);
+        buf.extend_from_slice(&names);
+        Ok(buf)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes()?)?)
+    }
+
+    pub fn get(&self, resource_type: OSType, id: i16) -> Option<&Resource> {
+        self.resources
+            .iter()
+            .find(|r| r.resource_type == resource_type && r.id == id)
+    }
+
+    pub fn get_mut(&mut self, resource_type: OSType, id: i16) -> Option<&mut Resource> {
+        self.resources
+            .iter_mut()
+            .find(|r| r.resource_type == resource_type && r.id == id)
+    }
+
+    /// Returns all the resources of a type.
+    pub fn of_type(&self, resource_type: OSType) -> impl Iterator<Item = &Resource> {
+        self.resources
+            .iter()
+            .filter(move |r| r.resource_type == resource_type)
+    }
+
+    /// Adds a resource, replacing any resource with the same type and ID.
+    pub fn insert(&mut self, resource: Resource) {
+        match self.get_mut(resource.resource_type, resource.id) {
+            Some(existing) => *existing = resource,
+            None => self.resources.push(resource),
+        }
+    }
+
+    pub fn remove(&mut self, resource_type: OSType, id: i16) -> Option<Resource> {
+        let index = self
+            .resources
+            .iter()
+            .position(|r| r.resource_type == resource_type && r.id == id)?;
+        Some(self.resources.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ResourceFork {
+        let mut fork = ResourceFork::default();
+        let mut string = Resource::new(OSType::new(b"STR "), 128, b"\x05Hello".to_vec());
+        string.name = Some("Greeting™".to_string());
+        string.attributes = resPurgeable;
+        fork.insert(string);
+        fork.insert(Resource::new(OSType::new(b"ICN#"), -16455, vec![0xff; 256]));
+        fork.insert(Resource::new(OSType::new(b"STR "), 129, Vec::new()));
+        fork
+    }
+
+    #[test]
+    fn test_resource_fork_roundtrip() {
+        let fork = sample();
+        let buf = fork.to_bytes().unwrap();
+        assert_eq!(be_u32(&buf, 0), 256);
+        let map_offset = be_u32(&buf, 4) as usize;
+        assert_eq!(&buf[map_offset..map_offset + 16], &buf[..16]);
+        // Two types, 'STR ' first.
+        assert_eq!(be_u16(&buf, map_offset + 28), 1);
+        assert_eq!(&buf[map_offset + 30..map_offset + 34], b"STR ");
+
+        let parsed = ResourceFork::from_slice(&buf).unwrap();
+        let ids: Vec<_> = parsed.resources.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![128, 129, -16455]);
+        let string = parsed.get(OSType::new(b"STR "), 128).unwrap();
+        assert_eq!(string.name.as_deref(), Some("Greeting™"));
+        assert_eq!(string.attributes, resPurgeable);
+        assert_eq!(string.data, b"\x05Hello");
+        assert_eq!(parsed.of_type(OSType::new(b"STR ")).count(), 2);
+        assert_eq!(
+            parsed.get(OSType::new(b"ICN#"), -16455).unwrap().data,
+            vec![0xff; 256]
+        );
+        assert_eq!(parsed.to_bytes().unwrap(), buf);
+
+        assert_eq!(
+            ResourceFork::from_slice(&[]).unwrap(),
+            ResourceFork::default()
+        );
+        let empty = ResourceFork::default().to_bytes().unwrap();
+        assert_eq!(
+            ResourceFork::from_slice(&empty).unwrap(),
+            ResourceFork::default()
+        );
+    }
+
+    #[test]
+    fn test_resource_fork_edit_and_errors() {
+        let mut fork = sample();
+        fork.insert(Resource::new(
+            OSType::new(b"STR "),
+            128,
+            b"\x03Bye".to_vec(),
+        ));
+        assert_eq!(fork.resources.len(), 3);
+        assert!(fork.remove(OSType::new(b"ICN#"), -16455).is_some());
+        assert!(fork.remove(OSType::new(b"ICN#"), -16455).is_none());
+
+        let buf = fork.to_bytes().unwrap();
+        match ResourceFork::from_slice(&buf[..buf.len() - 1]) {
+            Err(Error::Truncated { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        fork.resources[0].name = Some("日本".to_string());
+        assert!(fork.to_bytes().is_err());
+    }
+}
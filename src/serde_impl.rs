@@ -0,0 +1,311 @@
+//! Serde support for the FinderInfo structs.
+//!
+//! The representation is meant to be read and edited by people, e.g. in JSON manifests, while still
+//! round-tripping to exactly the same 32 bytes:
+//!
+//! * `OSType` is its `Display` form: four Mac OS Roman characters, with control characters escaped
+//!   as `\xNN` and backslashes as `\\`. Anything `OSType::from_str` accepts is also accepted when
+//!   deserializing, including escaped forms of printable characters.
+//! * `FinderFlags` and `ExtendedFinderFlags` are maps holding the raw value along with the names of
+//!   the flags which are set (and, for `FinderFlags`, the label color). When deserializing, either
+//!   the raw value or the names may be omitted; if both are given they have to agree.
+//! * `LabelColor` is its name, e.g. `"Red"`.
+//! * Reserved fields are always included.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::de::{self, Deserializer, Unexpected, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::{constants, ExtendedFinderFlags, FinderFlags, Flag, LabelColor, OSType};
+
+const EXTENDED_FINDER_FLAG_NAMES: [(u16, &str); 4] = [
+    (
+        constants::kExtendedFlagHasRoutingInfo,
+        "kExtendedFlagHasRoutingInfo",
+    ),
+    (
+        constants::kExtendedFlagObjectIsBusy,
+        "kExtendedFlagObjectIsBusy",
+    ),
+    (
+        constants::kExtendedFlagHasCustomBadge,
+        "kExtendedFlagHasCustomBadge",
+    ),
+    (
+        constants::kExtendedFlagsAreInvalid,
+        "kExtendedFlagsAreInvalid",
+    ),
+];
+
+fn flag_names(raw: u16, names: &[(u16, &'static str)]) -> Vec<&'static str> {
+    names
+        .iter()
+        .filter(|(mask, _)| raw & mask != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+fn flags_from_names<E: de::Error>(
+    flags: &[String],
+    names: &[(u16, &'static str)],
+) -> Result<u16, E> {
+    let mut raw = 0;
+    for flag in flags {
+        match names.iter().find(|(_, name)| name == flag) {
+            Some((mask, _)) => raw |= mask,
+            None => return Err(de::Error::custom(format!("unknown flag `{}`", flag))),
+        }
+    }
+    Ok(raw)
+}
+
+#[derive(Deserialize, Serialize)]
+struct FinderFlagsRepr {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    raw: Option<u16>,
+    #[serde(default)]
+    color: Option<LabelColor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flags: Option<Vec<String>>,
+}
+
+impl Serialize for FinderFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FinderFlagsRepr {
+            raw: Some(self.0),
+            color: self.color(),
+            flags: Some(self.iter().map(|flag| flag.name().to_string()).collect()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FinderFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FinderFlags, D::Error> {
+        let repr = FinderFlagsRepr::deserialize(deserializer)?;
+        let mut named = FinderFlags::empty();
+        for name in repr.flags.iter().flatten() {
+            named.insert(name.parse::<Flag>().map_err(de::Error::custom)?);
+        }
+        named.set_color(repr.color);
+        match repr.raw {
+            None => Ok(named),
+            Some(raw) => {
+                if repr.flags.is_some() && raw & FinderFlags::KNOWN != named.0 {
+                    return Err(de::Error::custom(format!(
+                        "raw value {:#06x} does not match the named flags",
+                        raw
+                    )));
+                }
+                Ok(FinderFlags(raw))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct ExtendedFinderFlagsRepr {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    raw: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flags: Option<Vec<String>>,
+}
+
+impl Serialize for ExtendedFinderFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let names = flag_names(self.0, &EXTENDED_FINDER_FLAG_NAMES);
+        ExtendedFinderFlagsRepr {
+            raw: Some(self.0),
+            flags: Some(names.into_iter().map(String::from).collect()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExtendedFinderFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ExtendedFinderFlags, D::Error> {
+        let repr = ExtendedFinderFlagsRepr::deserialize(deserializer)?;
+        let named = match &repr.flags {
+            Some(flags) => flags_from_names(flags, &EXTENDED_FINDER_FLAG_NAMES)?,
+            None => 0,
+        };
+        match repr.raw {
+            None => Ok(ExtendedFinderFlags(named)),
+            Some(raw) => {
+                let known = EXTENDED_FINDER_FLAG_NAMES
+                    .iter()
+                    .fold(0, |acc, (mask, _)| acc | mask);
+                if repr.flags.is_some() && raw & known != named {
+                    return Err(de::Error::custom(format!(
+                        "raw value {:#06x} does not match the named flags",
+                        raw
+                    )));
+                }
+                Ok(ExtendedFinderFlags(raw))
+            }
+        }
+    }
+}
+
+impl Serialize for LabelColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(LabelColor::to_str(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for LabelColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<LabelColor, D::Error> {
+        const COLORS: &[&str] = &["Gray", "Green", "Purple", "Blue", "Yellow", "Red", "Orange"];
+        let s = String::deserialize(deserializer)?;
+        LabelColor::from_str(&s).ok_or_else(|| de::Error::unknown_variant(&s, COLORS))
+    }
+}
+
+impl Serialize for OSType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct OSTypeVisitor;
+
+impl<'de> Visitor<'de> for OSTypeVisitor {
+    type Value = OSType;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a four character code or a 32-bit integer")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<OSType, E> {
+        s.parse()
+            .map_err(|_| de::Error::invalid_value(Unexpected::Str(s), &self))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<OSType, E> {
+        match u32::try_from(v) {
+            Ok(v) => Ok(OSType(v.to_be_bytes())),
+            Err(_) => Err(de::Error::invalid_value(Unexpected::Unsigned(v), &self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OSType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OSType, D::Error> {
+        deserializer.deserialize_any(OSTypeVisitor)
+    }
+}
+
+/// (De)serializes raw FinderInfo as a hex string.
+pub(crate) mod hex {
+    use serde::de::{self, Deserialize, Deserializer, Unexpected};
+    use serde::ser::Serializer;
+
+    pub fn serialize<S: Serializer>(buf: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        let s: String = buf.iter().map(|b| format!("{:02x}", b)).collect();
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let invalid = || de::Error::invalid_value(Unexpected::Str(&s), &"64 hexadecimal digits");
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(invalid());
+        }
+        let mut buf = [0u8; 32];
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::*;
+
+    #[test]
+    fn test_ostype_serde() {
+        let text = OSType(*b"TEXT");
+        assert_eq!(serde_json::to_value(text).unwrap(), json!("TEXT"));
+        let odd = OSType([0xa9, b'\\', 0x00, b'a']);
+        assert_eq!(serde_json::to_value(odd).unwrap(), json!("©\\\\\\x00a"));
+        assert_eq!(odd.to_string(), "©\\\\\\x00a");
+        for s in &["©\\\\\\x00a", "\\xA9\\\\\\x00a"] {
+            assert_eq!(serde_json::from_value::<OSType>(json!(s)).unwrap(), odd);
+        }
+        assert_eq!(
+            serde_json::from_value::<OSType>(json!(0x54455854)).unwrap(),
+            text
+        );
+        assert!(serde_json::from_value::<OSType>(json!("TEXTS")).is_err());
+        assert!(serde_json::from_value::<OSType>(json!("\\q123")).is_err());
+    }
+
+    #[test]
+    fn test_flags_serde() {
+        let mut flags = FinderFlags::from(0);
+        flags.set_color(Some(LabelColor::Red));
+        flags.set_is_invisible(true);
+        let value = serde_json::to_value(flags).unwrap();
+        assert_eq!(
+            value,
+            json!({"raw": 0x400c, "color": "Red", "flags": ["kIsInvisible"]})
+        );
+        assert_eq!(serde_json::from_value::<FinderFlags>(value).unwrap(), flags);
+
+        // The raw value may be left out...
+        let parsed: FinderFlags =
+            serde_json::from_value(json!({"color": "Red", "flags": ["kIsInvisible"]})).unwrap();
+        assert_eq!(parsed, flags);
+        // ...as may the names, in which case unknown bits survive.
+        let parsed: FinderFlags = serde_json::from_value(json!({"raw": 0x0220})).unwrap();
+        assert_eq!(u16::from(parsed), 0x0220);
+        // But they have to agree if both are present.
+        assert!(serde_json::from_value::<FinderFlags>(
+            json!({"raw": 0x400c, "color": "Blue", "flags": ["kIsInvisible"]})
+        )
+        .is_err());
+        assert!(serde_json::from_value::<FinderFlags>(json!({"flags": ["kBogus"]})).is_err());
+
+        let xflags = ExtendedFinderFlags::from(0x8100);
+        let value = serde_json::to_value(xflags).unwrap();
+        assert_eq!(
+            value,
+            json!({"raw": 0x8100, "flags": ["kExtendedFlagHasCustomBadge", "kExtendedFlagsAreInvalid"]})
+        );
+        assert_eq!(
+            serde_json::from_value::<ExtendedFinderFlags>(value).unwrap(),
+            xflags
+        );
+    }
+
+    #[test]
+    fn test_finderinfo_serde_roundtrip() {
+        let mut buf = [0u8; 32];
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = (i as u8).wrapping_mul(37);
+        }
+        for kind in &[FinderInfoKind::File, FinderInfoKind::Directory] {
+            let fi = FinderInfo::from_bytes_as(*kind, &buf);
+            let json = serde_json::to_string(&fi).unwrap();
+            let parsed: FinderInfo = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed.to_bytes(), buf);
+        }
+
+        let unknown = FinderInfo::Unknown(buf);
+        let value = serde_json::to_value(unknown).unwrap();
+        assert_eq!(
+            value,
+            json!({"Unknown": "00254a6f94b9de03284d7297bce1062b50759abfe4092e53789dc2e70c31567b"})
+        );
+        assert_eq!(
+            serde_json::from_value::<FinderInfo>(value).unwrap(),
+            unknown
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! Storage backends, abstracting over where the FinderInfo of a file lives.
+//!
+//! Depending on the filesystem and the tools which wrote it, FinderInfo may be in an extended
+//! attribute (`xattr::XattrBackend`, with the `xattr` feature), in an AppleDouble `._` sidecar
+//! file (`AppleDoubleStore`) or somewhere else entirely. Code written against `FinderInfoStore`
+//! doesn't need to care which.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::appledouble::{self, AppleDouble, EntryId};
+use crate::{kind_of_path, FinderInfo, Result};
+
+/// Somewhere FinderInfo can be read from and written to, keyed by the path of the file it
+/// belongs to.
+pub trait FinderInfoStore {
+    /// Reads the FinderInfo of `path`, or `None` if there is none.
+    fn get(&self, path: &Path) -> Result<Option<FinderInfo>>;
+
+    /// Sets the FinderInfo of `path`, replacing any there was.
+    fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()>;
+
+    /// Removes the FinderInfo of `path`, returning whether there was any.
+    fn remove(&self, path: &Path) -> Result<bool>;
+
+    /// Whether `path` has FinderInfo.
+    fn exists(&self, path: &Path) -> Result<bool> {
+        Ok(self.get(path)?.is_some())
+    }
+}
+
+/// Forwards the trait through smart pointers, so that stores can be shared.
+macro_rules! forward_store {
+    ($($ty:ty),*) => {$(
+        impl<T: FinderInfoStore + ?Sized> FinderInfoStore for $ty {
+            fn get(&self, path: &Path) -> Result<Option<FinderInfo>> {
+                (**self).get(path)
+            }
+
+            fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()> {
+                (**self).set(path, finder_info)
+            }
+
+            fn remove(&self, path: &Path) -> Result<bool> {
+                (**self).remove(path)
+            }
+
+            fn exists(&self, path: &Path) -> Result<bool> {
+                (**self).exists(path)
+            }
+        }
+    )*};
+}
+
+forward_store!(&T, Box<T>, Arc<T>);
+
+#[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+impl FinderInfoStore for crate::xattr::XattrBackend {
+    fn get(&self, path: &Path) -> Result<Option<FinderInfo>> {
+        self.read(path)
+    }
+
+    fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()> {
+        self.write(path, finder_info, crate::xattr::SetMode::CreateOrReplace)
+    }
+
+    fn remove(&self, path: &Path) -> Result<bool> {
+        crate::xattr::XattrBackend::remove(self, path)
+    }
+}
+
+/// Stores FinderInfo in AppleDouble `._` sidecar files next to the files they describe, as
+/// macOS does on filesystems without extended attributes.
+///
+/// Other entries in an existing sidecar, including the extended attributes stored after the
+/// FinderInfo, are preserved. All-zero FinderInfo counts as none, as macOS writes it when a
+/// sidecar is only needed for extended attributes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AppleDoubleStore;
+
+impl AppleDoubleStore {
+    fn read_sidecar(sidecar: &Path) -> Result<Option<AppleDouble>> {
+        match fs::read(sidecar) {
+            Ok(buf) => AppleDouble::from_slice(&buf).map(Some),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn sidecar_path(path: &Path) -> Result<PathBuf> {
+    appledouble::sidecar_path(path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name").into())
+}
+
+impl FinderInfoStore for AppleDoubleStore {
+    fn get(&self, path: &Path) -> Result<Option<FinderInfo>> {
+        let double = match AppleDoubleStore::read_sidecar(&sidecar_path(path)?)? {
+            Some(double) => double,
+            None => return Ok(None),
+        };
+        let finder_info = double.finder_info(kind_of_path(path)?)?;
+        Ok(finder_info.filter(|fi| fi.to_bytes() != [0u8; 32]))
+    }
+
+    fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()> {
+        let sidecar = sidecar_path(path)?;
+        let mut double = AppleDoubleStore::read_sidecar(&sidecar)?.unwrap_or_default();
+        double.set_finder_info(finder_info);
+        Ok(fs::write(&sidecar, double.to_bytes()?)?)
+    }
+
+    fn remove(&self, path: &Path) -> Result<bool> {
+        let sidecar = sidecar_path(path)?;
+        let mut double = match AppleDoubleStore::read_sidecar(&sidecar)? {
+            Some(double) => double,
+            None => return Ok(false),
+        };
+        let data = match double.entry_mut(EntryId::FINDER_INFO) {
+            Some(data) => data,
+            None => return Ok(false),
+        };
+        if data.len() > 32 {
+            // The extended attributes live in the same entry, so keep it and clear the FinderInfo.
+            let existed = data[..32] != [0u8; 32];
+            data[..32].copy_from_slice(&[0u8; 32]);
+            fs::write(&sidecar, double.to_bytes()?)?;
+            return Ok(existed);
+        }
+        let existed = data.iter().any(|&b| b != 0);
+        double.remove_entry(EntryId::FINDER_INFO);
+        if double.entries.is_empty() {
+            fs::remove_file(&sidecar)?;
+        } else {
+            fs::write(&sidecar, double.to_bytes()?)?;
+        }
+        Ok(existed)
+    }
+}
+
+/// Keeps FinderInfo in memory, for tests and dry runs.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<PathBuf, FinderInfo>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// The number of paths with FinderInfo.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl FinderInfoStore for MemoryStore {
+    fn get(&self, path: &Path) -> Result<Option<FinderInfo>> {
+        Ok(self.entries.lock().unwrap().get(path).copied())
+    }
+
+    fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), *finder_info);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<bool> {
+        Ok(self.entries.lock().unwrap().remove(path).is_some())
+    }
+}
+
+/// Several stores tried in order.
+///
+/// Reads return the FinderInfo from the first store which has any. Writes go to the first store
+/// only, and removal removes the FinderInfo from every store so that a stale copy further down
+/// doesn't reappear.
+#[derive(Default)]
+pub struct LayeredStore {
+    layers: Vec<Box<dyn FinderInfoStore>>,
+}
+
+impl fmt::Debug for LayeredStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayeredStore")
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+impl LayeredStore {
+    pub fn new() -> LayeredStore {
+        LayeredStore::default()
+    }
+
+    /// Adds a store below the existing ones.
+    pub fn with_layer<S: FinderInfoStore + 'static>(mut self, store: S) -> LayeredStore {
+        self.layers.push(Box::new(store));
+        self
+    }
+}
+
+impl FinderInfoStore for LayeredStore {
+    fn get(&self, path: &Path) -> Result<Option<FinderInfo>> {
+        for layer in &self.layers {
+            if let Some(finder_info) = layer.get(path)? {
+                return Ok(Some(finder_info));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes to the first store. Does nothing if there are no stores.
+    fn set(&self, path: &Path, finder_info: &FinderInfo) -> Result<()> {
+        match self.layers.first() {
+            Some(layer) => layer.set(path, finder_info),
+            None => Ok(()),
+        }
+    }
+
+    fn remove(&self, path: &Path) -> Result<bool> {
+        let mut removed = false;
+        for layer in &self.layers {
+            removed |= layer.remove(path)?;
+        }
+        Ok(removed)
+    }
+}
+
+/// Moves the FinderInfo of `path` from one store to another, e.g. from AppleDouble files onto
+/// extended attributes. Returns whether there was any to move.
+///
+/// The FinderInfo is written to `to` before it is removed from `from`, so an error part way
+/// through can leave it in both but never in neither.
+pub fn migrate<F, T>(from: &F, to: &T, path: &Path) -> Result<bool>
+where
+    F: FinderInfoStore + ?Sized,
+    T: FinderInfoStore + ?Sized,
+{
+    let finder_info = match from.get(path)? {
+        Some(finder_info) => finder_info,
+        None => return Ok(false),
+    };
+    to.set(path, &finder_info)?;
+    from.remove(path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::appledouble::ExtendedAttribute;
+    use crate::test_util::ScratchDir;
+    use crate::{FinderInfoFile, FinderInfoFolder, OSType};
+
+    fn text_file() -> FinderInfo {
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(b"TEXT");
+        FinderInfo::File(file)
+    }
+
+    #[test]
+    fn test_layered_store() {
+        let path = Path::new("/volume/file");
+        let top = Arc::new(MemoryStore::new());
+        let bottom = Arc::new(MemoryStore::new());
+        bottom.set(path, &text_file()).unwrap();
+
+        let layered = LayeredStore::new()
+            .with_layer(top.clone())
+            .with_layer(bottom.clone());
+
+        assert!(layered.exists(path).unwrap());
+        assert_eq!(layered.get(path).unwrap(), Some(text_file()));
+
+        let folder = FinderInfo::Directory(FinderInfoFolder::default());
+        layered.set(path, &folder).unwrap();
+        assert_eq!(layered.get(path).unwrap(), Some(folder));
+        assert_eq!(bottom.get(path).unwrap(), Some(text_file()));
+
+        assert!(layered.remove(path).unwrap());
+        assert!(top.is_empty() && bottom.is_empty());
+        assert!(!layered.remove(path).unwrap());
+        assert_eq!(LayeredStore::new().get(path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_appledouble_store() {
+        let dir = ScratchDir::new("store");
+        let path = dir.join("file");
+        fs::write(&path, b"").unwrap();
+        let sidecar = dir.join("._file");
+        let store = AppleDoubleStore;
+
+        assert_eq!(store.get(&path).unwrap(), None);
+        assert!(!store.remove(&path).unwrap());
+        store.set(&path, &text_file()).unwrap();
+        assert_eq!(store.get(&path).unwrap(), Some(text_file()));
+        assert!(store.remove(&path).unwrap());
+        assert!(!sidecar.exists());
+
+        // Extended attributes in the sidecar survive changes to the FinderInfo.
+        let mut double = AppleDouble::new();
+        double.set_finder_info(&text_file());
+        let attrs = vec![ExtendedAttribute::new("com.example.test", &b"value"[..])];
+        double.set_extended_attributes(&attrs).unwrap();
+        fs::write(&sidecar, double.to_bytes().unwrap()).unwrap();
+        assert!(store.remove(&path).unwrap());
+        assert!(!store.exists(&path).unwrap());
+        store.set(&path, &text_file()).unwrap();
+        let double = AppleDouble::from_slice(&fs::read(&sidecar).unwrap()).unwrap();
+        assert_eq!(double.extended_attributes().unwrap(), attrs);
+        assert_eq!(store.get(&path).unwrap(), Some(text_file()));
+    }
+}
@@ -0,0 +1,202 @@
+//! Finder tags, which replaced label colors in OS X 10.9.
+//!
+//! Tags are kept in the `com.apple.metadata:_kMDItemUserTags` extended attribute as a binary
+//! property list holding an array of strings. Each string is the tag name, optionally followed by
+//! a newline and the tag's color index: `"Red\n6"`. The index is the FinderInfo label color bits
+//! shifted right by one, so 0 is no color and 7 is orange.
+//!
+//! Current versions of macOS still set the FinderInfo label color to the color of the last colored
+//! tag, which is all that older clients see. `sync_label_color` keeps the two in step for files
+//! written by this crate.
+
+use std::io::{Read, Write};
+
+use crate::bplist::Value;
+use crate::{Error, FinderFlags, LabelColor, Result};
+
+/// Name of the extended attribute holding a file's tags.
+pub const ATTRIBUTE_NAME: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// A single Finder tag.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    /// The color index, 0 to 7. `None` if the tag string had no index, which is not the same as
+    /// an index of 0 when writing it back.
+    pub color: Option<u8>,
+}
+
+impl Tag {
+    /// Creates a tag with no color.
+    pub fn new(name: impl Into<String>) -> Tag {
+        Tag {
+            name: name.into(),
+            color: None,
+        }
+    }
+
+    /// Creates a tag with the given label color, named after the color as Finder does.
+    pub fn from_label_color(color: LabelColor) -> Tag {
+        Tag {
+            name: LabelColor::to_str(color).to_string(),
+            color: Some(LabelColor::to_u8(Some(color)) >> 1),
+        }
+    }
+
+    pub fn label_color(&self) -> Option<LabelColor> {
+        match self.color {
+            Some(index) if index <= 7 => LabelColor::from_u8(index << 1),
+            _ => None,
+        }
+    }
+
+    /// Parses a tag string. A suffix which isn't a color index is kept as part of the name.
+    pub fn parse(s: &str) -> Tag {
+        if let Some((name, index)) = s.rsplit_once('\n') {
+            if let Ok(color) = index.parse::<u8>() {
+                return Tag {
+                    name: name.to_string(),
+                    color: Some(color),
+                };
+            }
+        }
+        Tag::new(s)
+    }
+
+    pub fn encode(&self) -> String {
+        match self.color {
+            Some(color) => format!("{}\n{}", self.name, color),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// The tags on a file, in the order Finder shows them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Tags(pub Vec<Tag>);
+
+impl Tags {
+    /// Reads the value of the tags attribute to the end of `r`.
+    pub fn read<R: Read>(r: &mut R) -> Result<Tags> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        Tags::from_slice(&buf)
+    }
+
+    pub fn from_slice(buf: &[u8]) -> Result<Tags> {
+        let value = Value::from_slice(buf)?;
+        let values = value.as_array().ok_or(Error::Malformed {
+            reason: "tags are not an array",
+        })?;
+        values
+            .iter()
+            .map(|v| match v.as_str() {
+                Some(s) => Ok(Tag::parse(s)),
+                None => Err(Error::Malformed {
+                    reason: "tag is not a string",
+                }),
+            })
+            .collect::<Result<_>>()
+            .map(Tags)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        Value::Array(
+            self.0
+                .iter()
+                .map(|tag| Value::String(tag.encode()))
+                .collect(),
+        )
+        .to_bytes()
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> Result<()> {
+        Ok(w.write_all(&self.to_bytes())?)
+    }
+
+    /// The color of the last colored tag, which is the label color Finder shows.
+    pub fn label_color(&self) -> Option<LabelColor> {
+        self.0.iter().rev().find_map(Tag::label_color)
+    }
+}
+
+/// Reconciles the tags with the label color in the Finder flags.
+///
+/// If any tag has a color, the last one is copied into `flags`. Otherwise the label color from
+/// `flags`, as set by a client which predates tags, is added to the tags: either by coloring an
+/// uncolored tag with the color's name or by appending a new tag. The tags win when the two
+/// disagree, since they are what current versions of Finder display.
+pub fn sync_label_color(tags: &mut Tags, flags: &mut FinderFlags) {
+    if let Some(color) = tags.label_color() {
+        flags.set_color(Some(color));
+        return;
+    }
+    let color = match flags.color() {
+        Some(color) => color,
+        None => return,
+    };
+    let tag = Tag::from_label_color(color);
+    match tags.0.iter_mut().find(|t| t.name == tag.name) {
+        Some(existing) => existing.color = tag.color,
+        None => tags.0.push(tag),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_roundtrip() {
+        let tags = Tags(vec![
+            Tag::new("Work"),
+            Tag::from_label_color(LabelColor::Red),
+            Tag {
+                name: "Important".to_string(),
+                color: Some(0),
+            },
+            Tag::parse("Line\nbreak"),
+        ]);
+        assert_eq!(tags.0[1].encode(), "Red\n6");
+        assert_eq!(tags.0[3].name, "Line\nbreak");
+        let parsed = Tags::from_slice(&tags.to_bytes()).unwrap();
+        assert_eq!(parsed, tags);
+        assert_eq!(parsed.label_color(), Some(LabelColor::Red));
+
+        match Tags::from_slice(&Value::String("Red\n6".to_string()).to_bytes()) {
+            Err(Error::Malformed { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sync_label_color() {
+        // Tags set on a newer Mac take over the label color.
+        let mut tags = Tags(vec![Tag::parse("Green\n2"), Tag::parse("Blue\n4")]);
+        let mut flags = FinderFlags::default();
+        flags.set_color(Some(LabelColor::Red));
+        sync_label_color(&mut tags, &mut flags);
+        assert_eq!(flags.color(), Some(LabelColor::Blue));
+        assert_eq!(tags.0.len(), 2);
+
+        // A label set by an older client is added to the tags.
+        let mut tags = Tags(vec![Tag::new("Work")]);
+        let mut flags = FinderFlags::default();
+        flags.set_color(Some(LabelColor::Orange));
+        sync_label_color(&mut tags, &mut flags);
+        assert_eq!(tags.0[1].encode(), "Orange\n7");
+        assert_eq!(flags.color(), Some(LabelColor::Orange));
+
+        // An existing uncolored tag with the color's name is reused.
+        let mut tags = Tags(vec![Tag::new("Purple")]);
+        flags.set_color(Some(LabelColor::Purple));
+        sync_label_color(&mut tags, &mut flags);
+        assert_eq!(tags, Tags(vec![Tag::parse("Purple\n3")]));
+
+        let mut tags = Tags::default();
+        let mut flags = FinderFlags::default();
+        sync_label_color(&mut tags, &mut flags);
+        assert_eq!(tags, Tags::default());
+        assert_eq!(flags.color(), None);
+    }
+}
@@ -0,0 +1,56 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A directory under the temporary directory, removed when dropped so that a failing test doesn't
+/// leave it behind.
+#[derive(Debug)]
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// Creates an empty directory named after `test`, replacing any left over from an earlier run.
+    pub fn new(test: &str) -> ScratchDir {
+        let dir = std::env::temp_dir().join(format!("finder_info-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        ScratchDir(dir)
+    }
+
+    /// Like `new`, but returns `None` if the temporary directory's filesystem doesn't support
+    /// `user.` attributes. Any other error setting one fails the test.
+    #[cfg(all(feature = "xattr", any(target_os = "linux", target_os = "macos")))]
+    pub fn with_xattrs(test: &str) -> Option<ScratchDir> {
+        use crate::xattr::{self, SetMode};
+
+        let dir = ScratchDir::new(test);
+        match xattr::set(
+            &dir,
+            "user.finder_info.probe",
+            b"",
+            SetMode::CreateOrReplace,
+        ) {
+            Ok(()) => Some(dir),
+            Err(ref e) if xattr::is_unsupported(e) => {
+                eprintln!("skipping: {:?} doesn't support user xattrs", dir.0);
+                None
+            }
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,322 @@
+//! Reading and writing FinderInfo stored in extended attributes, on Linux and MacOS.
+//!
+//! MacOS keeps FinderInfo in the `com.apple.FinderInfo` attribute. Linux only allows unprivileged
+//! processes to use attributes in the `user.` namespace, so tools which copy Mac files there
+//! (rsync, Samba's `vfs_streams_xattr`, netatalk) store it as `user.com.apple.FinderInfo`.
+//!
+//! Paths are followed if they are symbolic links.
+
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::{kind_of_path, Error, FinderInfo, Result};
+
+/// Name of the FinderInfo attribute on MacOS.
+pub const MACOS_ATTRIBUTE_NAME: &str = "com.apple.FinderInfo";
+
+/// Name of the FinderInfo attribute on Linux.
+pub const LINUX_ATTRIBUTE_NAME: &str = "user.com.apple.FinderInfo";
+
+/// Name of the FinderInfo attribute on the platform the crate was built for.
+#[cfg(target_os = "macos")]
+pub const DEFAULT_ATTRIBUTE_NAME: &str = MACOS_ATTRIBUTE_NAME;
+#[cfg(not(target_os = "macos"))]
+pub const DEFAULT_ATTRIBUTE_NAME: &str = LINUX_ATTRIBUTE_NAME;
+
+#[cfg(target_os = "macos")]
+const ENOATTR: i32 = libc::ENOATTR;
+#[cfg(not(target_os = "macos"))]
+const ENOATTR: i32 = libc::ENODATA;
+
+/// How `set` treats an attribute which does or doesn't exist yet.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SetMode {
+    /// Create the attribute or replace its value.
+    #[default]
+    CreateOrReplace,
+    /// Fail with `io::ErrorKind::AlreadyExists` if the attribute exists (`XATTR_CREATE`).
+    Create,
+    /// Fail if the attribute doesn't exist (`XATTR_REPLACE`). See `is_missing`.
+    Replace,
+}
+
+impl SetMode {
+    fn flags(self) -> libc::c_int {
+        match self {
+            SetMode::CreateOrReplace => 0,
+            SetMode::Create => libc::XATTR_CREATE,
+            SetMode::Replace => libc::XATTR_REPLACE,
+        }
+    }
+}
+
+/// Whether `err` is the error `set` returns in `SetMode::Replace` when the attribute is missing.
+pub fn is_missing(err: &Error) -> bool {
+    match err {
+        Error::Io(e) => e.raw_os_error() == Some(ENOATTR),
+        _ => false,
+    }
+}
+
+/// Whether `err` means the filesystem doesn't support extended attributes, or not ones in the
+/// namespace of the name used.
+pub fn is_unsupported(err: &Error) -> bool {
+    match err {
+        Error::Io(e) => {
+            e.raw_os_error() == Some(libc::ENOTSUP) || e.raw_os_error() == Some(libc::EOPNOTSUPP)
+        }
+        _ => false,
+    }
+}
+
+fn c_string(bytes: &[u8]) -> Result<CString> {
+    CString::new(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e).into())
+}
+
+fn path_c_string(path: &Path) -> Result<CString> {
+    c_string(path.as_os_str().as_bytes())
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn sys_getxattr(
+    path: &CString,
+    name: &CString,
+    value: *mut libc::c_void,
+    size: usize,
+) -> isize {
+    libc::getxattr(path.as_ptr(), name.as_ptr(), value, size, 0, 0)
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn sys_getxattr(
+    path: &CString,
+    name: &CString,
+    value: *mut libc::c_void,
+    size: usize,
+) -> isize {
+    libc::getxattr(path.as_ptr(), name.as_ptr(), value, size)
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn sys_setxattr(path: &CString, name: &CString, value: &[u8], flags: libc::c_int) -> i32 {
+    libc::setxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr() as *const libc::c_void,
+        value.len(),
+        0,
+        flags,
+    )
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn sys_setxattr(path: &CString, name: &CString, value: &[u8], flags: libc::c_int) -> i32 {
+    libc::setxattr(
+        path.as_ptr(),
+        name.as_ptr(),
+        value.as_ptr() as *const libc::c_void,
+        value.len(),
+        flags,
+    )
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn sys_removexattr(path: &CString, name: &CString) -> i32 {
+    libc::removexattr(path.as_ptr(), name.as_ptr(), 0)
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn sys_removexattr(path: &CString, name: &CString) -> i32 {
+    libc::removexattr(path.as_ptr(), name.as_ptr())
+}
+
+/// Reads the attribute `name` of `path`, or `None` if it doesn't have one.
+pub fn get(path: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+    let c_path = path_c_string(path)?;
+    let c_name = c_string(name.as_bytes())?;
+    loop {
+        let size = unsafe { sys_getxattr(&c_path, &c_name, std::ptr::null_mut(), 0) };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(ENOATTR) {
+                return Ok(None);
+            }
+            return Err(err.into());
+        }
+        // One spare byte so that a value which grew since the size was read shows up as a
+        // short read of the wrong length rather than being silently truncated.
+        let mut buf = vec![0u8; size as usize + 1];
+        let ret = unsafe {
+            sys_getxattr(
+                &c_path,
+                &c_name,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::ERANGE) => continue,
+                Some(e) if e == ENOATTR => return Ok(None),
+                _ => return Err(err.into()),
+            }
+        }
+        if ret as usize > size as usize {
+            continue;
+        }
+        buf.truncate(ret as usize);
+        return Ok(Some(buf));
+    }
+}
+
+/// Sets the attribute `name` of `path` to `value`.
+pub fn set(path: &Path, name: &str, value: &[u8], mode: SetMode) -> Result<()> {
+    let c_path = path_c_string(path)?;
+    let c_name = c_string(name.as_bytes())?;
+    if unsafe { sys_setxattr(&c_path, &c_name, value, mode.flags()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Removes the attribute `name` from `path`, returning whether it was there.
+pub fn remove(path: &Path, name: &str) -> Result<bool> {
+    let c_path = path_c_string(path)?;
+    let c_name = c_string(name.as_bytes())?;
+    if unsafe { sys_removexattr(&c_path, &c_name) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(ENOATTR) {
+            return Ok(false);
+        }
+        return Err(err.into());
+    }
+    Ok(true)
+}
+
+/// Reads and writes FinderInfo in an extended attribute.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct XattrBackend {
+    name: String,
+}
+
+impl Default for XattrBackend {
+    fn default() -> XattrBackend {
+        XattrBackend::new()
+    }
+}
+
+impl XattrBackend {
+    /// Uses the platform's usual attribute name, `DEFAULT_ATTRIBUTE_NAME`.
+    pub fn new() -> XattrBackend {
+        XattrBackend::with_name(DEFAULT_ATTRIBUTE_NAME)
+    }
+
+    /// Uses the attribute `name`, e.g. `MACOS_ATTRIBUTE_NAME` on a Linux mount of an HFS+ volume.
+    pub fn with_name(name: impl Into<String>) -> XattrBackend {
+        XattrBackend { name: name.into() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reads the FinderInfo of `path`, or `None` if it has none. The FinderInfo is interpreted
+    /// according to whether `path` is a directory.
+    ///
+    /// A value which isn't exactly 32 bytes is an `Error::InvalidLength`.
+    pub fn read(&self, path: &Path) -> Result<Option<FinderInfo>> {
+        let buf = match get(path, &self.name)? {
+            Some(buf) => buf,
+            None => return Ok(None),
+        };
+        let buf = <[u8; 32]>::try_from(buf.as_slice()).map_err(|_| Error::InvalidLength {
+            expected: 32,
+            actual: buf.len(),
+        })?;
+        Ok(Some(FinderInfo::from_bytes_as(kind_of_path(path)?, &buf)))
+    }
+
+    pub fn write(&self, path: &Path, finder_info: &FinderInfo, mode: SetMode) -> Result<()> {
+        set(path, &self.name, &finder_info.to_bytes(), mode)
+    }
+
+    /// Removes the FinderInfo of `path`, returning whether it had any.
+    pub fn remove(&self, path: &Path) -> Result<bool> {
+        remove(path, &self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ScratchDir;
+    use crate::{FinderInfoFile, FinderInfoFolder, OSType};
+    use std::fs;
+
+    #[test]
+    fn test_xattr_roundtrip() {
+        let dir = match ScratchDir::with_xattrs("xattr-roundtrip") {
+            Some(dir) => dir,
+            None => return,
+        };
+        let path = dir.join("file");
+        fs::write(&path, b"").unwrap();
+        let backend = XattrBackend::with_name(LINUX_ATTRIBUTE_NAME);
+        assert!(backend.read(&path).unwrap().is_none());
+
+        let mut file = FinderInfoFile::default();
+        file.file_info.fileType = OSType::new(b"TEXT");
+        let fi = FinderInfo::File(file);
+        match backend.write(&path, &fi, SetMode::Replace) {
+            Err(ref e) if is_missing(e) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        backend.write(&path, &fi, SetMode::Create).unwrap();
+        match backend.write(&path, &fi, SetMode::Create) {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        backend.write(&path, &fi, SetMode::Replace).unwrap();
+        assert_eq!(backend.read(&path).unwrap(), Some(fi));
+
+        let folder = FinderInfo::Directory(FinderInfoFolder::default());
+        backend.write(&dir, &folder, SetMode::default()).unwrap();
+        assert_eq!(backend.read(&dir).unwrap(), Some(folder));
+
+        assert!(backend.remove(&path).unwrap());
+        assert!(!backend.remove(&path).unwrap());
+    }
+
+    #[test]
+    fn test_xattr_wrong_length() {
+        let dir = match ScratchDir::with_xattrs("xattr-length") {
+            Some(dir) => dir,
+            None => return,
+        };
+        let backend = XattrBackend::with_name(LINUX_ATTRIBUTE_NAME);
+        for &len in &[0usize, 5, 40] {
+            set(
+                &dir,
+                backend.name(),
+                &vec![0u8; len],
+                SetMode::CreateOrReplace,
+            )
+            .unwrap();
+            match backend.read(&dir) {
+                Err(Error::InvalidLength {
+                    expected: 32,
+                    actual,
+                }) if actual == len => {}
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        assert_eq!(
+            get(&dir, backend.name()).unwrap().map(|v| v.len()),
+            Some(40)
+        );
+    }
+}